@@ -0,0 +1,254 @@
+use std::f64::consts::PI;
+use std::time::{Duration, Instant};
+
+const STATE_FADE_DURATION: Duration = Duration::from_millis(200);
+const SHAKE_DURATION: Duration = Duration::from_millis(400);
+const LOCK_FADE_DURATION: Duration = Duration::from_millis(300);
+
+/// Distance in pixels of the first shake swing
+const SHAKE_AMPLITUDE: f64 = 12.0;
+const SHAKE_SWINGS: f64 = 3.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum AnimationKind {
+  /// Cross-fade of the indicator colours from the previous state
+  StateFade,
+  /// Horizontal shake of the state widgets after a failed attempt
+  Shake,
+  /// Widgets fading in once locked
+  FadeIn,
+  /// Widgets fading out before unlocking
+  FadeOut,
+  /// Everything darkening once the seat is idle
+  Dim,
+  /// Darkening undone on resume
+  Undim,
+}
+
+/// Values of every running animation at one point in time, settled values when none run
+#[derive(Clone, Copy)]
+pub struct AnimationFrame {
+  /// Progress of the fade from the previous state, 1 once settled
+  pub state_fade: f64,
+  /// Horizontal offset of the state widgets in pixels
+  pub shake: i32,
+  /// Opacity of all widgets
+  pub opacity: f64,
+  /// Progress of dimming, widgets hidden when idle fade out with it
+  pub dim: f64,
+  /// Opacity of the black overlay darkening the background and widgets
+  pub darken: f64,
+}
+
+impl Default for AnimationFrame {
+  fn default() -> Self {
+    Self {
+      state_fade: 1.0,
+      shake: 0,
+      opacity: 1.0,
+      dim: 0.0,
+      darken: 0.0,
+    }
+  }
+}
+
+struct Animation {
+  kind: AnimationKind,
+  start: Instant,
+  duration: Duration,
+}
+
+impl Animation {
+  fn progress(&self, now: Instant) -> f64 {
+    let elapsed = now.saturating_duration_since(self.start).as_secs_f64();
+    (elapsed / self.duration.as_secs_f64().max(f64::EPSILON)).min(1.0)
+  }
+}
+
+/// Time based transitions advanced on frame callbacks
+pub struct Animator {
+  animations: Vec<Animation>,
+  faded_out: bool,
+  dimmed: bool,
+  dim_duration: Duration,
+  dim_strength: f64,
+}
+
+impl Animator {
+  /// Dimming takes `dim_duration` and ends with an overlay of `dim_strength` opacity
+  pub fn new(dim_duration: Duration, dim_strength: f64) -> Self {
+    Self {
+      animations: Vec::new(),
+      faded_out: false,
+      dimmed: false,
+      dim_duration,
+      dim_strength,
+    }
+  }
+
+  fn duration(&self, kind: AnimationKind) -> Duration {
+    match kind {
+      AnimationKind::StateFade => STATE_FADE_DURATION,
+      AnimationKind::Shake => SHAKE_DURATION,
+      AnimationKind::FadeIn | AnimationKind::FadeOut => LOCK_FADE_DURATION,
+      AnimationKind::Dim | AnimationKind::Undim => self.dim_duration,
+    }
+  }
+
+  /// Start an animation, restarting it when already running
+  pub fn start(&mut self, kind: AnimationKind) {
+    self.animations.retain(|a| a.kind != kind);
+    self.animations.push(Animation {
+      kind,
+      start: Instant::now(),
+      duration: self.duration(kind),
+    });
+  }
+
+  /// Dim or undim, continuing from the current level when reversing halfway
+  pub fn set_dimmed(&mut self, dimmed: bool) {
+    let now = Instant::now();
+    let (kind, reverse) = if dimmed {
+      (AnimationKind::Dim, AnimationKind::Undim)
+    } else {
+      (AnimationKind::Undim, AnimationKind::Dim)
+    };
+    let level = self.dim_level(now);
+    if (dimmed && level >= 1.0) || (!dimmed && level <= 0.0) {
+      return;
+    }
+    let remaining = if dimmed { 1.0 - level } else { level };
+    self.animations.retain(|a| a.kind != kind && a.kind != reverse);
+    self.dimmed = false;
+    let duration = self.duration(kind);
+    self.animations.push(Animation {
+      kind,
+      start: now.checked_sub(duration.mul_f64(1.0 - remaining)).unwrap_or(now),
+      duration,
+    });
+  }
+
+  /// Progress of dimming at `now` between 0 and 1
+  fn dim_level(&self, now: Instant) -> f64 {
+    let running = self.animations.iter().find(|a| matches!(a.kind, AnimationKind::Dim | AnimationKind::Undim));
+    match running {
+      Some(a) if a.kind == AnimationKind::Dim => a.progress(now),
+      Some(a) => 1.0 - a.progress(now),
+      None if self.dimmed => 1.0,
+      None => 0.0,
+    }
+  }
+
+  /// Whether the animation of `kind` has been started and not pruned yet
+  pub fn is_running(&self, kind: AnimationKind) -> bool {
+    self.animations.iter().any(|a| a.kind == kind)
+  }
+
+  /// Drop the animations that have finished at `now`
+  pub fn prune(&mut self, now: Instant) {
+    if self.is_finished(AnimationKind::FadeOut, now) {
+      // Stay hidden until unlocked
+      self.faded_out = true;
+    }
+    if self.is_finished(AnimationKind::Dim, now) {
+      // Stay dimmed until resumed
+      self.dimmed = true;
+    }
+    self.animations.retain(|a| a.progress(now) < 1.0);
+  }
+
+  pub fn is_active(&self) -> bool {
+    !self.animations.is_empty()
+  }
+
+  /// Whether an animation of `kind` was started and has run its course at `now`
+  pub fn is_finished(&self, kind: AnimationKind, now: Instant) -> bool {
+    (kind == AnimationKind::FadeOut && self.faded_out)
+      || self.animations.iter().any(|a| a.kind == kind && a.progress(now) >= 1.0)
+  }
+
+  pub fn frame(&self, now: Instant) -> AnimationFrame {
+    let mut frame = AnimationFrame::default();
+    if self.faded_out {
+      frame.opacity = 0.0;
+    }
+    for animation in self.animations.iter() {
+      let t = animation.progress(now);
+      match animation.kind {
+        AnimationKind::StateFade => frame.state_fade = ease_out(t),
+        AnimationKind::Shake => {
+          // Damped sine swinging around the resting position
+          let swing = (t * SHAKE_SWINGS * 2.0 * PI).sin() * (1.0 - t);
+          frame.shake = (swing * SHAKE_AMPLITUDE).round() as i32;
+        }
+        AnimationKind::FadeIn => frame.opacity = ease_out(t),
+        AnimationKind::FadeOut => frame.opacity = 1.0 - ease_out(t),
+        // Read through the dim level, which also covers the settled states
+        AnimationKind::Dim | AnimationKind::Undim => {}
+      }
+    }
+    frame.dim = self.dim_level(now);
+    frame.darken = frame.dim * self.dim_strength;
+    frame
+  }
+}
+
+fn ease_out(t: f64) -> f64 {
+  1.0 - (1.0 - t).powi(3)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const DIM_DURATION: Duration = Duration::from_secs(10);
+
+  #[test]
+  fn reversing_dim_continues_from_level() {
+    let mut animator = Animator::new(DIM_DURATION, 0.6);
+    animator.set_dimmed(true);
+    // Pretend dimming has been running for half its duration
+    animator.animations[0].start -= DIM_DURATION / 2;
+    animator.set_dimmed(false);
+    assert!(animator.is_running(AnimationKind::Undim));
+    assert!(!animator.is_running(AnimationKind::Dim));
+    let now = Instant::now();
+    assert!((animator.dim_level(now) - 0.5).abs() < 0.01);
+    // Only the remaining half is left to undim, give or take the time spent since reversing
+    let later = now + DIM_DURATION / 2 + DIM_DURATION / 100;
+    assert_eq!(animator.dim_level(later), 0.0);
+    animator.prune(later);
+    assert!(!animator.is_active());
+    assert_eq!(animator.dim_level(later), 0.0);
+  }
+
+  #[test]
+  fn stays_dimmed_after_prune() {
+    let mut animator = Animator::new(DIM_DURATION, 0.6);
+    animator.set_dimmed(true);
+    let settled = Instant::now() + DIM_DURATION;
+    animator.prune(settled);
+    assert!(!animator.is_active());
+    assert_eq!(animator.dim_level(settled), 1.0);
+    let frame = animator.frame(settled);
+    assert_eq!(frame.dim, 1.0);
+    assert_eq!(frame.darken, 0.6);
+    // Dimming again is a no-op once settled
+    animator.set_dimmed(true);
+    assert!(!animator.is_active());
+  }
+
+  #[test]
+  fn undim_after_settled() {
+    let mut animator = Animator::new(DIM_DURATION, 0.6);
+    animator.set_dimmed(true);
+    animator.prune(Instant::now() + DIM_DURATION);
+    animator.set_dimmed(false);
+    let now = Instant::now();
+    assert!(animator.dim_level(now) > 0.99);
+    let settled = now + DIM_DURATION;
+    animator.prune(settled);
+    assert!(!animator.is_active());
+    assert_eq!(animator.frame(settled).darken, 0.0);
+  }
+}
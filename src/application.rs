@@ -1,27 +1,76 @@
-use wayland_client::protocol::{wl_compositor, wl_shm, wl_subcompositor};
-use wayland_protocols::ext::session_lock::v1::client::ext_session_lock_v1;
+use wayland_client::globals::GlobalList;
+use wayland_client::protocol::{wl_compositor, wl_output, wl_seat, wl_shm, wl_subcompositor, wl_surface};
+use wayland_client::{Connection, Proxy, QueueHandle};
+use wayland_protocols::ext::idle_notify::v1::client::{ext_idle_notification_v1, ext_idle_notifier_v1};
+use wayland_protocols::ext::session_lock::v1::client::{ext_session_lock_manager_v1, ext_session_lock_v1};
+use wayland_protocols_wlr::output_power_management::v1::client::{
+  zwlr_output_power_manager_v1, zwlr_output_power_v1,
+};
 
+use crate::animation::{AnimationKind, Animator};
 use crate::args::Args;
 use crate::auth::Authenticator;
+#[cfg(feature = "logind")]
+use crate::logind::Logind;
 use crate::output::AppOutput;
+use crate::screencopy::{self, Screenshot};
 use crate::seat::AppSeat;
-use std::time::Duration;
+use crate::shm::format::ShmFormats;
+use crate::shm::slot::SharedPool;
+use crate::surface::{AppSurface, FrameScope};
+use crate::widget::{self, WidgetContext};
+use std::time::{Duration, Instant};
+
+const SHM_COMPACT_INTERVAL: Duration = Duration::from_secs(60);
 
 pub struct Application {
   pub args: Args,
   pub seat: AppSeat,
   pub outputs: Vec<AppOutput>,
-  pub wl_shm: wl_shm::WlShm,
+  pub shm_pool: SharedPool,
+  pub output_power_manager: Option<zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1>,
   pub wl_compositor: wl_compositor::WlCompositor,
   pub wl_subcompositor: wl_subcompositor::WlSubcompositor,
-  pub ext_session_lock: ext_session_lock_v1::ExtSessionLockV1,
+  pub shm_formats: ShmFormats,
+  #[cfg(feature = "logind")]
+  pub logind: Option<Logind>,
 
+  connection: Connection,
+  globals: GlobalList,
   loop_handle: calloop::LoopHandle<'static, Self>,
+  qh: QueueHandle<Self>,
+  wl_shm: wl_shm::WlShm,
+  /// Every announced output by global name, kept between locks
+  wl_outputs: Vec<(u32, wl_output::WlOutput)>,
+  ext_session_lock_manager: ext_session_lock_manager_v1::ExtSessionLockManagerV1,
+  /// Lock currently requested or held
+  ext_session_lock: Option<ext_session_lock_v1::ExtSessionLockV1>,
+  /// Notifier and the seat whose idleness dims the lock screen
+  idle_notifier: Option<(ext_idle_notifier_v1::ExtIdleNotifierV1, wl_seat::WlSeat)>,
+  /// Only exists while locked so the compositor does not track idleness otherwise
+  idle_notification: Option<ext_idle_notification_v1::ExtIdleNotificationV1>,
+  /// Whether the compositor has confirmed the current lock
+  locked: bool,
+  /// Decoded once and reused by every lock
+  background_image: Option<cairo::ImageSurface>,
+  /// Decoded once for the user widget of every output
+  avatar: Option<cairo::ImageSurface>,
+  /// Why the config file last failed to reload
+  config_error: Option<String>,
+  animator: Animator,
   state: AppState,
+  previous_state: AppState,
   indicator_idle_timer: Option<calloop::RegistrationToken>,
+  input_clear_timer: Option<calloop::RegistrationToken>,
+  blank_timer: Option<calloop::RegistrationToken>,
+  /// Running while the grace period lets any input unlock
+  grace_timer: Option<calloop::RegistrationToken>,
+  blanked: bool,
   authenticator: Authenticator,
-  auth_sender: calloop::channel::Sender<bool>,
+  /// Results of authentication for the current lock, stale results are dropped with the channel
+  auth_channel: Option<(calloop::channel::Sender<bool>, calloop::RegistrationToken)>,
   password: String,
+  password_revealed: bool,
 }
 
 #[derive(Clone, Copy)]
@@ -36,112 +85,553 @@ pub enum AppState {
 impl Application {
   pub fn new(
     args: Args,
+    connection: Connection,
+    globals: GlobalList,
     loop_handle: calloop::LoopHandle<'static, Self>,
+    qh: QueueHandle<Self>,
     seat: AppSeat,
-    outputs: Vec<AppOutput>,
+    wl_outputs: Vec<(u32, wl_output::WlOutput)>,
+    shm_pool: SharedPool,
     wl_shm: wl_shm::WlShm,
     wl_compositor: wl_compositor::WlCompositor,
     wl_subcompositor: wl_subcompositor::WlSubcompositor,
-    ext_session_lock: ext_session_lock_v1::ExtSessionLockV1
+    ext_session_lock_manager: ext_session_lock_manager_v1::ExtSessionLockManagerV1,
+    output_power_manager: Option<zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1>,
+    idle_notifier: Option<(ext_idle_notifier_v1::ExtIdleNotifierV1, wl_seat::WlSeat)>,
   ) -> Application {
-    // Auth channel
-    let (auth_sender, auth_channel) = calloop::channel::channel::<bool>();
+    // Give memory back now and then when the pool wastes it
     loop_handle
-      .insert_source(auth_channel, |event, _, app| {
-        if let calloop::channel::Event::Msg(success) = event {
-          if success {
-            app.push_state(AppState::Success);
-          } else {
-            app.push_state(AppState::Invalid);
-            app.password.clear();
-          }
-        }
-      })
+      .insert_source(
+        calloop::timer::Timer::from_duration(SHM_COMPACT_INTERVAL),
+        |_, _, app| {
+          app.shm_pool.borrow_mut().compact_if_wasteful();
+          calloop::timer::TimeoutAction::ToDuration(SHM_COMPACT_INTERVAL)
+        },
+      )
       .unwrap();
 
+    let animator = new_animator(&args);
+    let background_image = args.background_image();
+    let avatar = args.user.then(widget::user::load_avatar).flatten();
+
     Application {
       args,
+      connection,
+      globals,
       loop_handle,
+      qh,
+      animator,
       seat,
-      outputs,
+      outputs: Vec::new(),
+      wl_outputs,
       state: AppState::Idle,
+      previous_state: AppState::Idle,
       password: String::with_capacity(12),
+      password_revealed: false,
       authenticator: Authenticator::new(),
-      auth_sender,
+      auth_channel: None,
       indicator_idle_timer: None,
+      input_clear_timer: None,
+      blank_timer: None,
+      grace_timer: None,
+      blanked: false,
+      shm_pool,
       wl_shm,
       wl_compositor,
       wl_subcompositor,
-      ext_session_lock
+      ext_session_lock_manager,
+      ext_session_lock: None,
+      idle_notifier,
+      idle_notification: None,
+      locked: false,
+      background_image,
+      avatar,
+      config_error: None,
+      shm_formats: ShmFormats::default(),
+      output_power_manager,
+      #[cfg(feature = "logind")]
+      logind: None,
+    }
+  }
+
+  /// Request a new session lock covering every output, does nothing while one is held
+  pub fn lock(&mut self) {
+    if self.ext_session_lock.is_some() {
+      return;
+    }
+    let wl_outputs: Vec<wl_output::WlOutput> = self.wl_outputs.iter().map(|(_, o)| o.clone()).collect();
+    // Screenshots have to be taken before the lock surfaces cover the outputs
+    let mut screenshots = if self.args.screenshot {
+      screencopy::capture_outputs(&self.connection, &self.globals, &self.wl_shm, &wl_outputs)
+    } else {
+      Vec::new()
+    };
+
+    self.ext_session_lock = Some(self.ext_session_lock_manager.lock(&self.qh, ()));
+    self.state = AppState::Idle;
+    self.previous_state = AppState::Idle;
+    self.animator = new_animator(&self.args);
+    self.animator.start(AnimationKind::FadeIn);
+    if let (Some((notifier, wl_seat)), Some(timeout)) = (self.idle_notifier.as_ref(), self.args.dim_timeout) {
+      let timeout = timeout.saturating_mul(1000);
+      self.idle_notification = Some(notifier.get_idle_notification(timeout, wl_seat, &self.qh, ()));
+    }
+
+    let (auth_sender, auth_channel) = calloop::channel::channel::<bool>();
+    let auth_token = self
+      .loop_handle
+      .insert_source(auth_channel, |event, _, app| {
+        if let calloop::channel::Event::Msg(success) = event {
+          if success {
+            app.push_state(AppState::Success);
+          } else {
+            app.password.clear();
+            app.push_state(AppState::Invalid);
+          }
+        }
+      })
+      .unwrap();
+    self.auth_channel = Some((auth_sender, auth_token));
+
+    for (i, wl_output) in wl_outputs.into_iter().enumerate() {
+      let screenshot = screenshots.get_mut(i).and_then(Option::take);
+      let image = screenshot.map(Screenshot::into_image).or(self.background_image.clone());
+      let output = self.create_output(wl_output, image);
+      self.outputs.push(output);
+    }
+    self.reset_blank_timer();
+    if let Some(grace) = self.args.grace {
+      self.grace_timer = Some(
+        self
+          .loop_handle
+          .insert_source(
+            calloop::timer::Timer::from_duration(Duration::from_secs(grace)),
+            |_, _, app| {
+              app.grace_timer = None;
+              app.render_state();
+              calloop::timer::TimeoutAction::Drop
+            },
+          )
+          .unwrap(),
+      );
+    }
+  }
+
+  /// Unlock without authentication while the grace period runs, returns whether it did
+  pub fn grace_unlock(&mut self) -> bool {
+    if self.grace_timer.is_none() {
+      return false;
+    }
+    self.unlock();
+    true
+  }
+
+  /// Whether a lock is requested or held
+  pub fn is_locking(&self) -> bool {
+    self.ext_session_lock.is_some()
+  }
+
+  /// The compositor confirmed the lock
+  pub fn set_locked(&mut self) {
+    self.locked = true;
+    if self.args.notify_locked {
+      println!("locked");
+    }
+    #[cfg(feature = "logind")]
+    if let Some(logind) = self.logind.as_mut() {
+      logind.locked();
+    }
+  }
+
+  /// Release the lock, or give it up when it was never confirmed, then drop the lock surfaces and typed input
+  pub fn unlock(&mut self) {
+    let Some(ext_session_lock) = self.ext_session_lock.take() else {
+      return;
+    };
+    if self.locked {
+      ext_session_lock.unlock_and_destroy();
+    } else {
+      ext_session_lock.destroy();
+    }
+    self.locked = false;
+    if let Some(idle_notification) = self.idle_notification.take() {
+      idle_notification.destroy();
+    }
+    // Lock surfaces are only destroyed once the lock is gone
+    self.outputs.clear();
+    self.password.clear();
+    self.password_revealed = false;
+    self.blanked = false;
+    let timers = [
+      self.indicator_idle_timer.take(),
+      self.input_clear_timer.take(),
+      self.blank_timer.take(),
+      self.grace_timer.take(),
+    ];
+    for timer in timers.into_iter().flatten() {
+      self.loop_handle.remove(timer);
+    }
+    if let Some((_, token)) = self.auth_channel.take() {
+      self.loop_handle.remove(token);
+    }
+    self.shm_pool.borrow_mut().compact();
+    #[cfg(feature = "logind")]
+    if let Some(logind) = self.logind.as_mut() {
+      logind.unlocked();
     }
   }
 
   pub fn password_clear(&mut self) {
     self.password.clear();
+    self.reset_input_clear_timer();
     self.push_state(AppState::Input);
   }
 
   pub fn password_push(&mut self, ch: char) {
     self.password.push(ch);
+    self.reset_input_clear_timer();
     self.push_state(AppState::Input);
   }
 
   pub fn password_pop(&mut self) {
     if self.password.pop().is_some() {
+      self.reset_input_clear_timer();
       self.push_state(AppState::Input);
     }
   }
 
   pub fn authenticate(&mut self) {
+    if let Some(timer) = self.input_clear_timer.take() {
+      self.loop_handle.remove(timer);
+    }
     self.push_state(AppState::Verifying);
-    self
-      .authenticator
-      .authenticate(self.password.clone(), self.auth_sender.clone());
+    if let Some((sender, _)) = self.auth_channel.as_ref() {
+      self.authenticator.authenticate(self.password.clone(), sender.clone());
+    }
   }
 
   pub fn current_state(&self) -> AppState {
     self.state
   }
 
-  fn push_state(&mut self, state: AppState) {
-    self.state = state;
-    for surface in self.outputs.iter_mut().map(|o| &mut o.surface) {
-      match state {
-        AppState::Success => surface.render_indicator_full(self.args.indicator_idle_color, self.args.bg_color),
-        AppState::Idle => surface.render_indicator_full(self.args.indicator_idle_color, self.args.bg_color),
-        AppState::Invalid => surface.render_indicator_full(self.args.indicator_wrong_color, self.args.bg_color),
-        AppState::Verifying => surface.render_indicator_full(self.args.indicator_verifying_color, self.args.bg_color),
-        AppState::Input => {
-          if self.password.len() == 0 {
-            surface.render_indicator_full(self.args.indicator_clear_color, self.args.bg_color)
-          } else {
-            surface.render_indicator_input(
-              self.password.len(),
-              self.args.indicator_input_cursor_color,
-              self.args.indicator_input_cursor_increment_color,
-              self.args.indicator_input_trail_color,
-              self.args.indicator_input_trail_increment_color,
-              self.args.bg_color,
-            )
-          }
+  /// Track a newly announced `wl_output`, covering it right away while locked
+  pub fn add_output(&mut self, name: u32, wl_output: wl_output::WlOutput) {
+    self.wl_outputs.push((name, wl_output.clone()));
+    if self.ext_session_lock.is_some() {
+      let mut output = self.create_output(wl_output, self.background_image.clone());
+      if self.blanked {
+        output.set_power(false);
+      }
+      self.outputs.push(output);
+    }
+  }
+
+  /// Forget the output of a removed global along with its lock surface
+  pub fn remove_output(&mut self, name: u32) {
+    let Some(i) = self.wl_outputs.iter().position(|(n, _)| *n == name) else {
+      return;
+    };
+    let (_, wl_output) = self.wl_outputs.remove(i);
+    self.outputs.retain(|output| output.wl_output != wl_output);
+    wl_output.release();
+  }
+
+  fn create_output(&self, wl_output: wl_output::WlOutput, image: Option<cairo::ImageSurface>) -> AppOutput {
+    let qh = &self.qh;
+    let mut surface = AppSurface::create(qh, &self.shm_pool, &self.wl_compositor, &self.wl_subcompositor, widget::build(&self.args, self.avatar.as_ref()));
+    surface.set_background_image(image);
+    let ext_session_lock = self.ext_session_lock.as_ref().unwrap();
+    let ext_session_lock_surface = ext_session_lock.get_lock_surface(surface.as_ref(), &wl_output, qh, surface.as_ref().clone());
+    let power = self.output_power_manager.as_ref().map(|manager| manager.get_output_power(&wl_output, qh, ()));
+    AppOutput::new(wl_output, ext_session_lock_surface, surface, power)
+  }
+
+  /// Note input on any seat, turning blanked outputs back on.
+  /// Returns whether outputs were blanked, in which case the input only woke them
+  pub fn wake(&mut self) -> bool {
+    let blanked = self.blanked;
+    if blanked {
+      self.blanked = false;
+      for output in self.outputs.iter_mut() {
+        output.set_power(true);
+      }
+    }
+    self.reset_blank_timer();
+    blanked
+  }
+
+  /// Forget an output power object the compositor can no longer use
+  pub fn output_power_failed(&mut self, power: &zwlr_output_power_v1::ZwlrOutputPowerV1) {
+    let output = self.outputs.iter_mut().find(|o| o.power.as_ref() == Some(power));
+    if let Some(power) = output.and_then(|o| o.power.take()) {
+      power.destroy();
+    }
+  }
+
+  /// Dim everything while the seat is idle and restore it on resume
+  pub fn set_idle(&mut self, idle: bool) {
+    self.animator.set_dimmed(idle);
+    self.schedule_frames();
+  }
+
+  /// Authenticated and done fading out
+  pub fn ready_to_unlock(&self) -> bool {
+    self.is_locking()
+      && matches!(self.state, AppState::Success)
+      && self.animator.is_finished(AnimationKind::FadeOut, Instant::now())
+  }
+
+  pub fn set_password_revealed(&mut self, revealed: bool) {
+    if self.password_revealed != revealed {
+      self.password_revealed = revealed;
+      self.render_state();
+    }
+  }
+
+  /// Resize the surface of an output and redraw all of its layers
+  pub fn configure_surface(&mut self, wl_surface: &wl_surface::WlSurface, width: u32, height: u32) {
+    let mut outputs = std::mem::take(&mut self.outputs);
+    let ctx = self.widget_context(Instant::now());
+    let surface = outputs
+      .iter_mut()
+      .map(|o| &mut o.surface)
+      .find(|surface| surface.as_ref().id() == wl_surface.id());
+    if let Some(surface) = surface {
+      surface.set_dimensions(width, height);
+      surface.render_bg(&self.args.background(), &self.shm_formats, ctx.frame.darken);
+      surface.render_widgets(&ctx);
+      surface.as_ref().commit();
+      if self.animator.is_active() {
+        surface.schedule_frame(&self.qh);
+      }
+    }
+    self.outputs = outputs;
+  }
+
+  /// Advance the animations on an output whose frame callback is done
+  pub fn frame_done(&mut self, wl_surface: &wl_surface::WlSurface) {
+    let now = Instant::now();
+    // Checked before pruning so the settled values are drawn too
+    let dimming = self.animator.is_running(AnimationKind::Dim) || self.animator.is_running(AnimationKind::Undim);
+    let fading = self.animator.is_running(AnimationKind::FadeIn) || self.animator.is_running(AnimationKind::FadeOut);
+    let scope = if fading || (dimming && self.args.dim_strength > 0.0) {
+      FrameScope::All
+    } else if dimming {
+      FrameScope::Dimming
+    } else {
+      FrameScope::State
+    };
+    self.animator.prune(now);
+    let animating = self.animator.is_active();
+    let mut outputs = std::mem::take(&mut self.outputs);
+    let ctx = self.widget_context(now);
+    let surface = outputs
+      .iter_mut()
+      .map(|o| &mut o.surface)
+      .find(|surface| surface.as_ref().id() == wl_surface.id());
+    if let Some(surface) = surface {
+      if dimming {
+        surface.render_bg(&self.args.background(), &self.shm_formats, ctx.frame.darken);
+      }
+      surface.render_frame(&ctx, &self.qh, animating, scope);
+    }
+    self.outputs = outputs;
+  }
+
+  /// Parse the config file again and apply it. On failure the current options stay and a notice shows why
+  pub fn reload_config(&mut self) {
+    let path = self.args.config_path();
+    match Args::parse_with_config(path.as_deref()) {
+      Ok(args) => {
+        let restart = self.args.reload_from(args);
+        if !restart.is_empty() {
+          eprintln!("Restart minlock to apply {}", restart.join(", "));
         }
-      };
+        self.config_error = None;
+        self.reload();
+      }
+      Err(err) => {
+        eprintln!("Failed to reload the config: {}", err);
+        self.config_error = Some(err);
+        // Unchanged widgets skip their commit, so only the notice appears
+        self.render_all();
+      }
+    }
+  }
+
+  /// Load the theme again and redraw every output, keeping the lock and the typed password
+  pub fn reload(&mut self) {
+    self.background_image = self.args.background_image();
+    self.avatar = self.args.user.then(widget::user::load_avatar).flatten();
+    for output in self.outputs.iter_mut() {
+      let surface = &mut output.surface;
+      if self.args.screenshot {
+        // The screenshot taken when locking stays, only its effects change
+        surface.invalidate_background();
+      } else {
+        surface.set_background_image(self.background_image.clone());
+      }
+      surface.set_widgets(&self.qh, &self.wl_compositor, &self.wl_subcompositor, widget::build(&self.args, self.avatar.as_ref()));
     }
+    self.render_all();
+  }
+
+  /// Redraw periodically refreshed widgets and return when the next one is due
+  pub fn render_due(&mut self) -> Option<Instant> {
+    let mut outputs = std::mem::take(&mut self.outputs);
+    let ctx = self.widget_context(Instant::now());
+    let next = outputs.iter_mut().filter_map(|o| o.surface.render_due(&ctx)).min();
+    self.outputs = outputs;
+    next
+  }
+
+  fn render_state(&mut self) {
+    let mut outputs = std::mem::take(&mut self.outputs);
+    let ctx = self.widget_context(Instant::now());
+    let animating = self.animator.is_active();
+    for surface in outputs.iter_mut().map(|o| &mut o.surface) {
+      surface.render_state(&ctx);
+      if animating {
+        surface.schedule_frame(&self.qh);
+      }
+    }
+    self.outputs = outputs;
+  }
+
+  /// Redraw every layer of every output
+  fn render_all(&mut self) {
+    let mut outputs = std::mem::take(&mut self.outputs);
+    let ctx = self.widget_context(Instant::now());
+    let background = self.args.background();
+    for surface in outputs.iter_mut().map(|o| &mut o.surface) {
+      surface.render_bg(&background, &self.shm_formats, ctx.frame.darken);
+      surface.render_widgets(&ctx);
+      surface.as_ref().commit();
+    }
+    self.outputs = outputs;
+  }
+
+  /// What the widgets draw at `now`. It borrows all of `self`, so callers move `outputs` out while drawing
+  fn widget_context(&self, now: Instant) -> WidgetContext<'_> {
+    WidgetContext {
+      state: self.state,
+      previous_state: self.previous_state,
+      password: &self.password,
+      password_revealed: self.password_revealed,
+      grace: self.grace_timer.is_some(),
+      notice: self.config_error.as_deref(),
+      frame: self.animator.frame(now),
+    }
+  }
+
+  /// Ask every output for a frame callback to advance newly started animations
+  fn schedule_frames(&mut self) {
+    for surface in self.outputs.iter_mut().map(|o| &mut o.surface) {
+      surface.schedule_frame(&self.qh);
+    }
+  }
+
+  /// Switch state and start the transitions into it
+  fn set_state(&mut self, state: AppState) {
+    self.previous_state = self.state;
+    self.state = state;
+    self.animator.start(AnimationKind::StateFade);
+    match state {
+      AppState::Invalid => self.animator.start(AnimationKind::Shake),
+      AppState::Success => self.animator.start(AnimationKind::FadeOut),
+      _ => {}
+    }
+  }
+
+  /// How long a state is shown before going idle, None for states that stay until changed
+  fn state_duration(&self, state: AppState) -> Option<Duration> {
+    let millis = match state {
+      // Success stays until the fade out is done and the session unlocks
+      AppState::Verifying | AppState::Idle | AppState::Success => return None,
+      AppState::Invalid => self.args.invalid_duration,
+      AppState::Input if self.password.is_empty() => self.args.clear_duration,
+      AppState::Input => self.args.idle_timeout,
+    };
+    Some(Duration::from_millis(millis))
+  }
+
+  /// Restart the countdown to turning the outputs off
+  fn reset_blank_timer(&mut self) {
+    if let Some(timer) = self.blank_timer.take() {
+      self.loop_handle.remove(timer);
+    }
+    let (Some(timeout), Some(_)) = (self.args.blank_timeout, self.output_power_manager.as_ref()) else {
+      return;
+    };
+    if self.ext_session_lock.is_none() {
+      // Outputs are only blanked while locked
+      return;
+    }
+    self.blank_timer = Some(
+      self
+        .loop_handle
+        .insert_source(
+          calloop::timer::Timer::from_duration(Duration::from_secs(timeout)),
+          |_, _, app| {
+            app.blank_timer = None;
+            app.blanked = true;
+            for output in app.outputs.iter_mut() {
+              output.set_power(false);
+            }
+            calloop::timer::TimeoutAction::Drop
+          },
+        )
+        .unwrap(),
+    );
+  }
+
+  /// Restart the countdown to forgetting the typed input after a key press
+  fn reset_input_clear_timer(&mut self) {
+    if let Some(timer) = self.input_clear_timer.take() {
+      self.loop_handle.remove(timer);
+    }
+    let Some(timeout) = self.args.input_clear_timeout else {
+      return;
+    };
+    if self.password.is_empty() {
+      return;
+    }
+    self.input_clear_timer = Some(
+      self
+        .loop_handle
+        .insert_source(
+          calloop::timer::Timer::from_duration(Duration::from_secs(timeout)),
+          |_, _, app| {
+            app.input_clear_timer = None;
+            if !matches!(app.state, AppState::Verifying) {
+              app.password.clear();
+              app.password_revealed = false;
+              app.render_state();
+            }
+            calloop::timer::TimeoutAction::Drop
+          },
+        )
+        .unwrap(),
+    );
+  }
+
+  fn push_state(&mut self, state: AppState) {
+    self.set_state(state);
+    self.render_state();
     // Reset idle timer
     if let Some(timer) = self.indicator_idle_timer {
       self.loop_handle.remove(timer);
     }
-    if !matches!(state, AppState::Verifying) {
+    if let Some(duration) = self.state_duration(state) {
       self.indicator_idle_timer = Some(
         self
           .loop_handle
           .insert_source(
-            calloop::timer::Timer::from_duration(Duration::from_secs(2)),
+            calloop::timer::Timer::from_duration(duration),
             |_, _, app| {
-              for output in app.outputs.iter_mut() {
-                app.state = AppState::Idle;
-                output.surface.render_indicator_full(app.args.indicator_idle_color, app.args.bg_color);
-              }
+              app.set_state(AppState::Idle);
+              app.password_revealed = false;
+              app.render_state();
               calloop::timer::TimeoutAction::Drop
             },
           )
@@ -150,3 +640,7 @@ impl Application {
     }
   }
 }
+
+fn new_animator(args: &Args) -> Animator {
+  Animator::new(Duration::from_millis(args.dim_duration), args.dim_strength.clamp(0.0, 1.0))
+}
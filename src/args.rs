@@ -1,12 +1,73 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
 use clap::Parser;
 use hex_color::{HexColor, ParseHexColorError};
+use xkbcommon::xkb;
+
+use crate::layout::{parse_offset, parse_position, Anchor, Layout, Placement};
+use crate::render::background::{parse_gradient, parse_pattern, Background, Gradient, Pattern};
+use crate::render::effects::{parse_effect, BlurKind, Effect};
+use crate::render::indicator::{Indicator, IndicatorStyle, InputColors};
+use crate::render::text::TextAlign;
 
 #[derive(Parser)]
-#[command(version, about, long_about=None, after_help = "All <color> options are in RRGGBB format")]
+#[command(version, about, long_about=None, args_override_self = true, after_help = "All <color> options are in RRGGBB or RRGGBBAA format\nAll <font> options are Pango font descriptions such as \"Inter Bold 48\", <size> applies when none is given")]
 pub struct Args {
+  /// File with one option per line as on the command line without the leading dashes, such as bg-color=000000.
+  /// Defaults to minlock/config in $XDG_CONFIG_HOME, options on the command line take precedence
+  #[arg(long, value_name = "path")]
+  pub config: Option<PathBuf>,
+
   #[arg(long, value_name="color", value_parser=parse_color, default_value="04030B", hide_default_value=true)]
   pub bg_color: Color,
 
+  /// Gradient drawn over the background colour, linear:<angle>:<stops> or radial:<stops>.
+  /// Stops are comma separated <color>[@<0..1>], spread evenly when positions are left out
+  #[arg(long, value_name = "gradient", value_parser = parse_gradient)]
+  pub bg_gradient: Option<Gradient>,
+
+  /// Pattern drawn over the background gradient, one of noise:<0..1>, checker:<px>:<color>:<color>
+  /// or stripes:<px>:<angle>:<color>:<color>
+  #[arg(long, value_name = "pattern", value_parser = parse_pattern)]
+  pub bg_pattern: Option<Pattern>,
+
+  /// PNG image drawn over the background colour, scaled to cover each output
+  #[arg(long, value_name = "path")]
+  pub bg_image: Option<PathBuf>,
+
+  /// Effect applied to the whole background, can be repeated to build a chain.
+  /// One of blur:<radius>, box-blur:<radius>, pixelate:<px>, greyscale, brightness:<-1..1>,
+  /// contrast:<factor>, overlay:<color>:<0..1> or vignette:<0..1>
+  #[arg(long, value_name = "effect", value_parser = parse_effect)]
+  pub bg_effect: Vec<Effect>,
+
+  /// Use a screenshot of each output as background, requires wlr-screencopy
+  #[arg(long)]
+  pub screenshot: bool,
+
+  #[arg(long, value_name = "radius", default_value = "8", hide_default_value = true)]
+  pub screenshot_blur: usize,
+
+  #[arg(long, value_name = "kind", value_enum, default_value = "gaussian", hide_default_value = true)]
+  pub screenshot_blur_kind: BlurKind,
+
+  /// Size of the pixelation blocks, 0 disables it
+  #[arg(long, value_name = "px", default_value = "0", hide_default_value = true)]
+  pub screenshot_pixelate: usize,
+
+  /// Darkening of the corners between 0 and 1
+  #[arg(long, value_name = "strength", default_value = "0.0", hide_default_value = true)]
+  pub screenshot_vignette: f64,
+
+  #[arg(long, value_name="color", value_parser=parse_color)]
+  pub screenshot_tint: Option<Color>,
+
+  #[arg(long, value_name = "strength", default_value = "0.3", hide_default_value = true)]
+  pub screenshot_tint_strength: f64,
+
   #[arg(long, value_name="color", value_parser=parse_color, default_value="FAFAFA", hide_default_value=true)]
   pub clock_color: Color,
 
@@ -16,6 +77,49 @@ pub struct Args {
   #[arg(long, value_name = "size", default_value = "60.0", hide_default_value = true)]
   pub clock_font_size: f64,
 
+  #[arg(long, value_name = "anchor", value_enum, default_value = "center", hide_default_value = true)]
+  pub clock_anchor: Anchor,
+
+  #[arg(long, value_name = "x,y", value_parser = parse_offset, allow_hyphen_values = true)]
+  pub clock_offset: Option<(i32, i32)>,
+
+  #[arg(long, value_name = "px", default_value = "0", hide_default_value = true)]
+  pub clock_margin: i32,
+
+  #[arg(long, value_name = "x%,y%", value_parser = parse_position)]
+  pub clock_position: Option<(f64, f64)>,
+
+  #[arg(long, value_name = "style", value_enum, default_value = "blocks", hide_default_value = true)]
+  pub indicator_style: IndicatorStyle,
+
+  /// Number of blocks or ring segments
+  #[arg(long, value_name = "count", value_parser = clap::value_parser!(u32).range(1..), default_value = "4", hide_default_value = true)]
+  pub indicator_count: u32,
+
+  /// Block or dot size, or ring thickness
+  #[arg(long, value_name = "px", default_value = "10", hide_default_value = true)]
+  pub indicator_size: u32,
+
+  /// Space between blocks, dots or ring segments
+  #[arg(long, value_name = "px", default_value = "30", hide_default_value = true)]
+  pub indicator_spacing: u32,
+
+  #[arg(long, value_name = "px", default_value = "40", hide_default_value = true)]
+  pub indicator_ring_radius: u32,
+
+  /// Place independently of the clock instead of stacking below it
+  #[arg(long, value_name = "anchor", value_enum)]
+  pub indicator_anchor: Option<Anchor>,
+
+  #[arg(long, value_name = "x,y", value_parser = parse_offset, allow_hyphen_values = true)]
+  pub indicator_offset: Option<(i32, i32)>,
+
+  #[arg(long, value_name = "px", default_value = "0", hide_default_value = true)]
+  pub indicator_margin: i32,
+
+  #[arg(long, value_name = "x%,y%", value_parser = parse_position)]
+  pub indicator_position: Option<(f64, f64)>,
+
   #[arg(long, value_name="color", value_parser=parse_color, default_value="333333", hide_default_value=true)]
   pub indicator_idle_color: Color,
 
@@ -28,6 +132,10 @@ pub struct Args {
   #[arg(long, value_name="color", value_parser=parse_color, default_value="998033", hide_default_value=true)]
   pub indicator_verifying_color: Color,
 
+  /// Indicator colour while the grace period lets any input unlock
+  #[arg(long, value_name="color", value_parser=parse_color, default_value="4C994C", hide_default_value=true)]
+  pub indicator_grace_color: Color,
+
   #[arg(long, value_name="color", value_parser=parse_color, default_value="606060", hide_default_value=true)]
   pub indicator_input_cursor_color: Color,
 
@@ -39,6 +147,404 @@ pub struct Args {
 
   #[arg(long, value_name="color", value_parser=parse_color, default_value="191919", hide_default_value=true)]
   pub indicator_input_trail_increment_color: Color,
+
+  /// Show the typed password as a masked text field below the indicator
+  #[arg(long)]
+  pub password_field: bool,
+
+  #[arg(long, value_name="color", value_parser=parse_color, default_value="FAFAFA", hide_default_value=true)]
+  pub password_color: Color,
+
+  #[arg(long, value_name = "font", default_value = "sans", hide_default_value = true)]
+  pub password_font: String,
+
+  #[arg(long, value_name = "size", default_value = "24.0", hide_default_value = true)]
+  pub password_font_size: f64,
+
+  /// Place independently of the clock instead of stacking below it
+  #[arg(long, value_name = "anchor", value_enum)]
+  pub password_anchor: Option<Anchor>,
+
+  #[arg(long, value_name = "x,y", value_parser = parse_offset, allow_hyphen_values = true)]
+  pub password_offset: Option<(i32, i32)>,
+
+  #[arg(long, value_name = "px", default_value = "0", hide_default_value = true)]
+  pub password_margin: i32,
+
+  #[arg(long, value_name = "x%,y%", value_parser = parse_position)]
+  pub password_position: Option<(f64, f64)>,
+
+  /// Key chord that reveals the password field while held, e.g. ctrl+shift+r
+  #[arg(long, value_name = "chord", value_parser=parse_key_chord)]
+  pub password_reveal_key: Option<KeyChord>,
+
+  /// Text shown below the other widgets, wrapped to the output width
+  #[arg(long, value_name = "text")]
+  pub message: Option<String>,
+
+  /// Read the message from a file instead
+  #[arg(long, value_name = "path", conflicts_with = "message")]
+  pub message_file: Option<PathBuf>,
+
+  #[arg(long, value_name="color", value_parser=parse_color, default_value="FAFAFA", hide_default_value=true)]
+  pub message_color: Color,
+
+  #[arg(long, value_name = "font", default_value = "sans", hide_default_value = true)]
+  pub message_font: String,
+
+  #[arg(long, value_name = "size", default_value = "16.0", hide_default_value = true)]
+  pub message_font_size: f64,
+
+  #[arg(long, value_name = "align", value_enum, default_value = "center", hide_default_value = true)]
+  pub message_align: TextAlign,
+
+  /// Maximum line width in percent of the output width
+  #[arg(long, value_name = "percent", value_parser = clap::value_parser!(u32).range(1..), default_value = "80", hide_default_value = true)]
+  pub message_max_width: u32,
+
+  #[arg(long, value_name = "anchor", value_enum)]
+  pub message_anchor: Option<Anchor>,
+
+  #[arg(long, value_name = "x,y", value_parser = parse_offset, allow_hyphen_values = true)]
+  pub message_offset: Option<(i32, i32)>,
+
+  #[arg(long, value_name = "px", default_value = "0", hide_default_value = true)]
+  pub message_margin: i32,
+
+  #[arg(long, value_name = "x%,y%", value_parser = parse_position)]
+  pub message_position: Option<(f64, f64)>,
+
+  /// Show the full name and avatar of the locking user
+  #[arg(long)]
+  pub user: bool,
+
+  #[arg(long, value_name="color", value_parser=parse_color, default_value="FAFAFA", hide_default_value=true)]
+  pub user_color: Color,
+
+  #[arg(long, value_name = "font", default_value = "sans", hide_default_value = true)]
+  pub user_font: String,
+
+  #[arg(long, value_name = "size", default_value = "20.0", hide_default_value = true)]
+  pub user_font_size: f64,
+
+  #[arg(long, value_name = "px", default_value = "96", hide_default_value = true)]
+  pub user_avatar_size: u32,
+
+  /// Background of the initials shown when there is no avatar image
+  #[arg(long, value_name="color", value_parser=parse_color, default_value="338080", hide_default_value=true)]
+  pub user_avatar_color: Color,
+
+  #[arg(long, value_name = "anchor", value_enum)]
+  pub user_anchor: Option<Anchor>,
+
+  #[arg(long, value_name = "x,y", value_parser = parse_offset, allow_hyphen_values = true)]
+  pub user_offset: Option<(i32, i32)>,
+
+  #[arg(long, value_name = "px", default_value = "0", hide_default_value = true)]
+  pub user_margin: i32,
+
+  #[arg(long, value_name = "x%,y%", value_parser = parse_position)]
+  pub user_position: Option<(f64, f64)>,
+
+  /// Show battery capacity and charging state
+  #[arg(long)]
+  pub battery: bool,
+
+  #[arg(long, value_name="color", value_parser=parse_color, default_value="FAFAFA", hide_default_value=true)]
+  pub battery_color: Color,
+
+  #[arg(long, value_name="color", value_parser=parse_color, default_value="B24C4C", hide_default_value=true)]
+  pub battery_low_color: Color,
+
+  /// Capacity in percent at or below which the low colour is used
+  #[arg(long, value_name = "percent", default_value = "15", hide_default_value = true)]
+  pub battery_low_threshold: u32,
+
+  #[arg(long, value_name = "font", default_value = "sans", hide_default_value = true)]
+  pub battery_font: String,
+
+  #[arg(long, value_name = "size", default_value = "18.0", hide_default_value = true)]
+  pub battery_font_size: f64,
+
+  #[arg(long, value_name = "seconds", value_parser = clap::value_parser!(u64).range(1..), default_value = "30", hide_default_value = true)]
+  pub battery_refresh: u64,
+
+  #[arg(long, value_name = "path", default_value = "/sys/class/power_supply", hide_default_value = true)]
+  pub battery_sysfs_root: PathBuf,
+
+  #[arg(long, value_name = "anchor", value_enum, default_value = "top-right", hide_default_value = true)]
+  pub battery_anchor: Anchor,
+
+  #[arg(long, value_name = "x,y", value_parser = parse_offset, allow_hyphen_values = true)]
+  pub battery_offset: Option<(i32, i32)>,
+
+  #[arg(long, value_name = "px", default_value = "20", hide_default_value = true)]
+  pub battery_margin: i32,
+
+  #[arg(long, value_name = "x%,y%", value_parser = parse_position)]
+  pub battery_position: Option<(f64, f64)>,
+
+  /// How long a failed attempt is shown before going idle, 2000 by default
+  #[arg(long, value_name = "ms", value_parser = clap::value_parser!(u64).range(100..), default_value = "2000", hide_default_value = true)]
+  pub invalid_duration: u64,
+
+  /// How long clearing the input is shown before going idle, 2000 by default
+  #[arg(long, value_name = "ms", value_parser = clap::value_parser!(u64).range(100..), default_value = "2000", hide_default_value = true)]
+  pub clear_duration: u64,
+
+  /// How long after the last key press the indicator goes idle while typing, 2000 by default
+  #[arg(long, value_name = "ms", value_parser = clap::value_parser!(u64).range(100..), default_value = "2000", hide_default_value = true)]
+  pub idle_timeout: u64,
+
+  /// Forget typed input after this many seconds without a key press
+  #[arg(long, value_name = "seconds", value_parser = clap::value_parser!(u64).range(1..))]
+  pub input_clear_timeout: Option<u64>,
+
+  /// Unlock without a password on any key or pointer input during this many seconds after locking
+  #[arg(long, value_name = "seconds", value_parser = clap::value_parser!(u64).range(1..))]
+  pub grace: Option<u64>,
+
+  /// Keep the lock on pointer motion during the grace period, buttons and scrolling still unlock
+  #[arg(long, requires = "grace")]
+  pub grace_ignore_pointer: bool,
+
+  /// Dim the lock screen and hide the clock after this many seconds idle, requires ext-idle-notify
+  #[arg(long, value_name = "seconds", value_parser = clap::value_parser!(u32).range(1..))]
+  pub dim_timeout: Option<u32>,
+
+  /// How long dimming takes, 2000 by default
+  #[arg(long, value_name = "ms", default_value = "2000", hide_default_value = true)]
+  pub dim_duration: u64,
+
+  /// Darkness of the dimmed lock screen between 0 and 1, 0.7 by default
+  #[arg(long, value_name = "0..1", default_value = "0.7", hide_default_value = true)]
+  pub dim_strength: f64,
+
+  /// Turn outputs off after this many seconds without input, requires wlr-output-power-management
+  #[arg(long, value_name = "seconds", value_parser = clap::value_parser!(u64).range(1..))]
+  pub blank_timeout: Option<u64>,
+
+  /// Most shared memory used for buffers across all outputs, 256 by default
+  #[arg(long, value_name = "MiB", value_parser = clap::value_parser!(u64).range(1..), default_value = "256", hide_default_value = true)]
+  pub shm_cap: u64,
+
+  /// Stay connected between locks and lock on SIGUSR2 or a `lock` command on the control socket
+  #[arg(long)]
+  pub daemon: bool,
+
+  /// Control socket of the daemon, defaults to minlock.sock in $XDG_RUNTIME_DIR
+  #[arg(long, value_name = "path", requires = "daemon")]
+  pub control_socket: Option<PathBuf>,
+
+  /// Stay running and lock on logind sleep and lock requests, holding a sleep delay inhibitor
+  #[cfg(feature = "logind")]
+  #[arg(long)]
+  pub logind: bool,
+
+  /// Print a line once the session is locked, used by the logind helper
+  #[arg(long, hide = true)]
+  pub notify_locked: bool,
+}
+
+impl Args {
+  /// Parse the config file followed by the command line, exiting on errors
+  pub fn load() -> Args {
+    let cli = Args::parse();
+    match Args::parse_with_config(cli.config_path().as_deref()) {
+      Ok(args) => args,
+      Err(err) => {
+        eprintln!("Invalid config: {}", err);
+        std::process::exit(2);
+      }
+    }
+  }
+
+  /// Parse the options of the config file at `path`, when it exists, followed by the command line
+  pub fn parse_with_config(path: Option<&Path>) -> Result<Args, String> {
+    let mut cli = std::env::args_os();
+    let mut argv: Vec<OsString> = cli.next().into_iter().collect();
+    if let Some(path) = path {
+      match fs::read_to_string(path) {
+        Ok(text) => argv.extend(config_options(&text).map(OsString::from)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(format!("{}: {}", path.display(), err)),
+      }
+    }
+    argv.extend(cli);
+    // Only the first line of clap's message, the rest is usage help
+    Args::try_parse_from(argv).map_err(|err| {
+      let message = err.to_string();
+      message.lines().next().unwrap_or_default().trim_start_matches("error: ").to_string()
+    })
+  }
+
+  /// Whether to run the logind helper. Lock clients it spawns are passed `--notify-locked` and lock instead
+  #[cfg(feature = "logind")]
+  pub fn runs_logind_helper(&self) -> bool {
+    self.logind && !self.daemon && !self.notify_locked
+  }
+
+  /// Config file given on the command line or the default one, which may not exist
+  pub fn config_path(&self) -> Option<PathBuf> {
+    self.config.clone().or_else(|| {
+      let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+      Some(dir.join("minlock").join("config"))
+    })
+  }
+
+  /// Take over the theme, widget and layout options of `reloaded`. Options only read at startup keep their
+  /// current value, the flags of those that would have changed are returned
+  pub fn reload_from(&mut self, mut reloaded: Args) -> Vec<&'static str> {
+    let mut restart = Vec::new();
+    macro_rules! keep {
+      ($($field:ident => $flag:literal),* $(,)?) => {$(
+        if reloaded.$field != self.$field {
+          restart.push($flag);
+        }
+        reloaded.$field = self.$field.clone();
+      )*};
+    }
+    keep!(
+      daemon => "--daemon",
+      control_socket => "--control-socket",
+      grace => "--grace",
+      blank_timeout => "--blank-timeout",
+      dim_timeout => "--dim-timeout",
+      shm_cap => "--shm-cap",
+    );
+    #[cfg(feature = "logind")]
+    keep!(logind => "--logind");
+    *self = reloaded;
+    restart
+  }
+
+  pub fn background(&self) -> Background {
+    Background {
+      color: self.bg_color,
+      gradient: self.bg_gradient.clone(),
+      pattern: self.bg_pattern,
+      effects: self.background_effects(),
+    }
+  }
+
+  /// Effects applied to the background, starting with the screenshot options
+  fn background_effects(&self) -> Vec<Effect> {
+    let mut effects = Vec::new();
+    if self.screenshot {
+      effects.push(Effect::Pixelate(self.screenshot_pixelate));
+      effects.push(Effect::Blur(self.screenshot_blur_kind, self.screenshot_blur));
+      if let Some(color) = self.screenshot_tint {
+        effects.push(Effect::Overlay(color, self.screenshot_tint_strength));
+      }
+      effects.push(Effect::Vignette(self.screenshot_vignette));
+    }
+    effects.extend(self.bg_effect.iter().copied());
+    effects
+  }
+
+  pub fn background_image(&self) -> Option<cairo::ImageSurface> {
+    let path = self.bg_image.as_ref()?;
+    let image = fs::File::open(path)
+      .map_err(|err| err.to_string())
+      .and_then(|mut file| cairo::ImageSurface::create_from_png(&mut file).map_err(|err| err.to_string()));
+    match image {
+      Ok(image) => Some(image),
+      Err(err) => {
+        eprintln!("Failed to load background image {}: {}", path.display(), err);
+        None
+      }
+    }
+  }
+
+  /// Message from the command line, or read from the message file
+  pub fn message_text(&self) -> Option<String> {
+    let path = match self.message_file.as_ref() {
+      Some(path) => path,
+      None => return self.message.clone(),
+    };
+    match fs::read_to_string(path) {
+      Ok(text) => Some(text),
+      Err(err) => {
+        eprintln!("Failed to read message file {}: {}", path.display(), err);
+        None
+      }
+    }
+  }
+
+  pub fn layout(&self) -> Layout {
+    let placement = |anchor: Option<Anchor>, offset: Option<(i32, i32)>, margin, position: Option<(f64, f64)>| {
+      // An explicit position alone implies centring on it
+      let anchor = anchor.or(position.map(|_| Anchor::Center))?;
+      Some(Placement {
+        anchor,
+        offset: offset.unwrap_or((0, 0)),
+        margin,
+        position,
+      })
+    };
+    Layout {
+      clock: placement(
+        Some(self.clock_anchor),
+        self.clock_offset,
+        self.clock_margin,
+        self.clock_position,
+      )
+      .unwrap(),
+      indicator: placement(
+        self.indicator_anchor,
+        self.indicator_offset,
+        self.indicator_margin,
+        self.indicator_position,
+      ),
+      password: placement(
+        self.password_anchor,
+        self.password_offset,
+        self.password_margin,
+        self.password_position,
+      ),
+      user: placement(self.user_anchor, self.user_offset, self.user_margin, self.user_position),
+      message: placement(
+        self.message_anchor,
+        self.message_offset,
+        self.message_margin,
+        self.message_position,
+      ),
+      battery: placement(
+        Some(self.battery_anchor),
+        self.battery_offset,
+        self.battery_margin,
+        self.battery_position,
+      )
+      .unwrap(),
+    }
+  }
+
+  pub fn indicator(&self) -> Indicator {
+    Indicator {
+      style: self.indicator_style,
+      count: self.indicator_count as usize,
+      size: self.indicator_size,
+      spacing: self.indicator_spacing,
+      ring_radius: self.indicator_ring_radius,
+      input_colors: InputColors {
+        cursor: self.indicator_input_cursor_color,
+        cursor_increment: self.indicator_input_cursor_increment_color,
+        trail: self.indicator_input_trail_color,
+        trail_increment: self.indicator_input_trail_increment_color,
+      },
+    }
+  }
+}
+
+/// Options in a config file as command line arguments, skipping blank lines and # comments
+fn config_options(text: &str) -> impl Iterator<Item = String> + '_ {
+  text
+    .lines()
+    .map(str::trim)
+    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    .map(|line| format!("--{}", line))
 }
 
 #[derive(Clone, Copy)]
@@ -46,13 +552,87 @@ pub struct Color {
   pub r: f64,
   pub g: f64,
   pub b: f64,
+  pub a: f64,
 }
 
-fn parse_color(str: &str) -> Result<Color, ParseHexColorError> {
+/// Parse RRGGBB or RRGGBBAA, colours without alpha are opaque
+pub fn parse_color(str: &str) -> Result<Color, ParseHexColorError> {
   let hex_color = HexColor::parse(&(String::from("#") + str))?;
   Ok(Color {
     r: f64::from(hex_color.r) / 255f64,
     b: f64::from(hex_color.b) / 255f64,
     g: f64::from(hex_color.g) / 255f64,
+    a: f64::from(hex_color.a) / 255f64,
   })
 }
+
+#[derive(Clone)]
+pub struct KeyChord {
+  pub modifiers: Vec<&'static str>,
+  pub keysym: xkb::Keysym,
+}
+
+fn parse_key_chord(str: &str) -> Result<KeyChord, String> {
+  let mut parts: Vec<&str> = str.split('+').map(str::trim).collect();
+  let key = parts.pop().filter(|key| !key.is_empty()).ok_or("missing key")?;
+  let modifiers = parts
+    .into_iter()
+    .map(|modifier| match modifier.to_lowercase().as_str() {
+      "ctrl" | "control" => Ok(xkb::MOD_NAME_CTRL),
+      "shift" => Ok(xkb::MOD_NAME_SHIFT),
+      "alt" | "mod1" => Ok(xkb::MOD_NAME_ALT),
+      "super" | "logo" | "mod4" => Ok(xkb::MOD_NAME_LOGO),
+      _ => Err(format!("unknown modifier '{}'", modifier)),
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+  let keysym = xkb::keysym_from_name(key, xkb::KEYSYM_CASE_INSENSITIVE);
+  if keysym == xkb::keysyms::KEY_NoSymbol {
+    return Err(format!("unknown key '{}'", key));
+  }
+  Ok(KeyChord { modifiers, keysym })
+}
+
+impl KeyChord {
+  /// Compare keysyms ignoring case, as held modifiers such as shift change the reported keysym
+  pub fn matches_key(&self, keysym: xkb::Keysym) -> bool {
+    xkb::keysym_get_name(keysym).eq_ignore_ascii_case(&xkb::keysym_get_name(self.keysym))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reload_keeps_startup_options() {
+    let mut args = Args::try_parse_from(["minlock", "--daemon", "--shm-cap", "64"]).unwrap();
+    let reloaded = Args::try_parse_from(["minlock", "--shm-cap", "32", "--clock-font-size", "20"]).unwrap();
+    assert_eq!(args.reload_from(reloaded), vec!["--daemon", "--shm-cap"]);
+    assert!(args.daemon);
+    assert_eq!(args.shm_cap, 64);
+    assert_eq!(args.clock_font_size, 20.0);
+
+    let reloaded = Args::try_parse_from(["minlock", "--daemon", "--shm-cap", "64"]).unwrap();
+    assert!(args.reload_from(reloaded).is_empty());
+    assert_eq!(args.clock_font_size, 60.0);
+  }
+
+  #[cfg(feature = "logind")]
+  #[test]
+  fn spawned_lock_client_is_no_helper() {
+    let args = Args::try_parse_from(["minlock", "--logind"]).unwrap();
+    assert!(args.runs_logind_helper());
+    let args = Args::try_parse_from(["minlock", "--logind", "--notify-locked"]).unwrap();
+    assert!(!args.runs_logind_helper());
+    let args = Args::try_parse_from(["minlock", "--logind", "--daemon"]).unwrap();
+    assert!(!args.runs_logind_helper());
+  }
+
+  #[test]
+  fn message_max_width_is_positive() {
+    assert!(Args::try_parse_from(["minlock", "--message-max-width", "0"]).is_err());
+    assert!(Args::try_parse_from(["minlock", "--message-max-width", "-5"]).is_err());
+    let args = Args::try_parse_from(["minlock", "--message-max-width", "50"]).unwrap();
+    assert_eq!(args.message_max_width, 50);
+  }
+}
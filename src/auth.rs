@@ -1,4 +1,4 @@
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, PoisonError};
 use std::thread;
 use users::{get_current_uid, get_user_by_uid};
 
@@ -18,13 +18,75 @@ impl Authenticator {
   }
 
   pub fn authenticate(&self, password: String, sender: calloop::channel::Sender<bool>) {
-    let pam_auth = Arc::clone(&self.pam_auth);
     let username = self.username.clone();
-    thread::spawn(move || {
-      let mut pam_auth = pam_auth.lock().unwrap();
+    verify(&self.pam_auth, sender, move |pam_auth| {
       pam_auth.get_handler().set_credentials(&username, password);
-      let success = pam_auth.authenticate().is_ok();
-      sender.send(success).unwrap();
+      pam_auth.authenticate().is_ok()
     });
   }
 }
+
+/// Run `check` on its own thread with `state` locked and send the result.
+/// The lock may be gone by the time it finishes, the result is dropped then
+fn verify<T: Send + 'static>(
+  state: &Arc<Mutex<T>>,
+  sender: calloop::channel::Sender<bool>,
+  check: impl FnOnce(&mut T) -> bool + Send + 'static,
+) -> thread::JoinHandle<()> {
+  let state = Arc::clone(state);
+  thread::spawn(move || {
+    // A panic elsewhere must not stop every later attempt
+    let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+    let success = check(&mut state);
+    let _ = sender.send(success);
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn receiver_dropped_during_check() {
+    let state = Arc::new(Mutex::new(0));
+    let (sender, channel) = calloop::channel::channel();
+    let check = verify(&state, sender, move |attempts| {
+      // Unlocked while verifying
+      drop(channel);
+      *attempts += 1;
+      true
+    });
+    check.join().unwrap();
+    assert!(!state.is_poisoned());
+
+    let (sender, _channel) = calloop::channel::channel();
+    verify(&state, sender, |attempts| {
+      *attempts += 1;
+      false
+    })
+    .join()
+    .unwrap();
+    assert_eq!(*state.lock().unwrap(), 2);
+  }
+
+  #[test]
+  fn recovers_from_poisoned_lock() {
+    let state = Arc::new(Mutex::new(0));
+    let poisoner = Arc::clone(&state);
+    let _ = thread::spawn(move || {
+      let _guard = poisoner.lock().unwrap();
+      panic!("poison the lock");
+    })
+    .join();
+    assert!(state.is_poisoned());
+
+    let (sender, _channel) = calloop::channel::channel();
+    verify(&state, sender, |attempts| {
+      *attempts += 1;
+      true
+    })
+    .join()
+    .unwrap();
+    assert_eq!(*state.lock().unwrap_or_else(PoisonError::into_inner), 1);
+  }
+}
@@ -0,0 +1,66 @@
+use std::ffi::{CString, OsString};
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use calloop::generic::Generic;
+use calloop::{Interest, LoopHandle, Mode, PostAction};
+
+use crate::application::Application;
+
+/// Reload the config whenever the file at `path` is written or replaced
+pub fn watch(path: &Path, loop_handle: &LoopHandle<'static, Application>) -> io::Result<()> {
+  let name: OsString = path
+    .file_name()
+    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?
+    .to_owned();
+  let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+  let dir = CString::new(dir.as_os_str().as_bytes())?;
+
+  let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+  if fd < 0 {
+    return Err(io::Error::last_os_error());
+  }
+  let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+  // Editors often save by renaming a new file over the old one, so the directory is watched
+  let watch = unsafe { libc::inotify_add_watch(fd.as_raw_fd(), dir.as_ptr(), libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO) };
+  if watch < 0 {
+    return Err(io::Error::last_os_error());
+  }
+
+  loop_handle
+    .insert_source(Generic::new(fd, Interest::READ, Mode::Level), move |_, fd, app| {
+      if read_events(fd.as_ref(), &name) {
+        app.reload_config();
+      }
+      Ok(PostAction::Continue)
+    })
+    .map_err(|err| io::Error::from(err.error))?;
+  Ok(())
+}
+
+/// Drain the pending events and return whether one of them was about `name`
+fn read_events(fd: &OwnedFd, name: &OsString) -> bool {
+  let mut buf = [0u8; 4096];
+  let mut changed = false;
+  loop {
+    let len = unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+    if len <= 0 {
+      return changed;
+    }
+    // Each event header is followed by its name, padded with nul bytes
+    let mut offset = 0;
+    while offset < len as usize {
+      let event = unsafe { std::ptr::read_unaligned(buf.as_ptr().add(offset).cast::<libc::inotify_event>()) };
+      let start = offset + mem::size_of::<libc::inotify_event>();
+      let end = start + event.len as usize;
+      let event_name = buf[start..end].split(|b| *b == 0).next().unwrap_or_default();
+      if event_name == name.as_bytes() {
+        changed = true;
+      }
+      offset = end;
+    }
+  }
+}
@@ -0,0 +1,59 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use calloop::generic::Generic;
+use calloop::{Interest, LoopHandle, Mode, PostAction};
+
+use crate::application::Application;
+
+/// Longest a client may block the event loop while sending its command
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// `minlock.sock` in the runtime directory, None without one since anyone could take the name in a shared directory
+pub fn default_path() -> Option<PathBuf> {
+  std::env::var_os("XDG_RUNTIME_DIR").map(|dir| PathBuf::from(dir).join("minlock.sock"))
+}
+
+/// Accept one command per connection on a Unix socket at `path`, answered with `ok` or an error.
+/// The only command is `lock`
+pub fn listen(path: &Path, loop_handle: &LoopHandle<'static, Application>) -> io::Result<()> {
+  if UnixStream::connect(path).is_ok() {
+    return Err(io::Error::new(io::ErrorKind::AddrInUse, "another daemon is listening"));
+  }
+  // Left behind by a daemon that did not exit cleanly
+  let _ = std::fs::remove_file(path);
+  let listener = UnixListener::bind(path)?;
+  // Only the user may lock through it, even when the socket is placed somewhere shared
+  std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+  listener.set_nonblocking(true)?;
+  loop_handle
+    .insert_source(Generic::new(listener, Interest::READ, Mode::Level), |_, listener, app| {
+      while let Ok((stream, _)) = listener.as_ref().accept() {
+        handle(stream, app);
+      }
+      Ok(PostAction::Continue)
+    })
+    .map_err(|err| io::Error::from(err.error))?;
+  Ok(())
+}
+
+fn handle(stream: UnixStream, app: &mut Application) {
+  let mut line = String::new();
+  let read = stream
+    .set_read_timeout(Some(READ_TIMEOUT))
+    .and_then(|_| BufReader::new(&stream).read_line(&mut line));
+  if read.is_err() {
+    return;
+  }
+  let reply = match line.trim() {
+    "lock" => {
+      app.lock();
+      String::from("ok")
+    }
+    command => format!("unknown command '{}'", command),
+  };
+  let _ = writeln!(&stream, "{}", reply);
+}
@@ -0,0 +1,193 @@
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Anchor {
+  TopLeft,
+  Top,
+  TopRight,
+  Left,
+  Center,
+  Right,
+  BottomLeft,
+  Bottom,
+  BottomRight,
+}
+
+impl Anchor {
+  /// Fractions of the width and height at which the anchor point lies
+  fn factors(&self) -> (f64, f64) {
+    match self {
+      Anchor::TopLeft => (0.0, 0.0),
+      Anchor::Top => (0.5, 0.0),
+      Anchor::TopRight => (1.0, 0.0),
+      Anchor::Left => (0.0, 0.5),
+      Anchor::Center => (0.5, 0.5),
+      Anchor::Right => (1.0, 0.5),
+      Anchor::BottomLeft => (0.0, 1.0),
+      Anchor::Bottom => (0.5, 1.0),
+      Anchor::BottomRight => (1.0, 1.0),
+    }
+  }
+}
+
+#[derive(Clone, Copy)]
+pub struct Placement {
+  pub anchor: Anchor,
+  pub offset: (i32, i32),
+  pub margin: i32,
+  /// Position of the anchor point in percent of the output size, overriding the output edges
+  pub position: Option<(f64, f64)>,
+}
+
+impl Default for Placement {
+  fn default() -> Self {
+    Self {
+      anchor: Anchor::Center,
+      offset: (0, 0),
+      margin: 0,
+      position: None,
+    }
+  }
+}
+
+impl Placement {
+  /// Position of the top left corner of an element of `size` on an output of `output` size
+  pub fn place(&self, output: (u32, u32), size: (u32, u32)) -> (i32, i32) {
+    let (fx, fy) = self.anchor.factors();
+    let (ref_x, ref_y) = match self.position {
+      Some((px, py)) => (output.0 as f64 * px / 100.0, output.1 as f64 * py / 100.0),
+      None => {
+        // Margins push the element away from the edges it is anchored to
+        let margin = self.margin as f64;
+        let span_x = output.0 as f64 - 2.0 * margin;
+        let span_y = output.1 as f64 - 2.0 * margin;
+        (margin + span_x * fx, margin + span_y * fy)
+      }
+    };
+    let x = ref_x - size.0 as f64 * fx;
+    let y = ref_y - size.1 as f64 * fy;
+    (x.round() as i32 + self.offset.0, y.round() as i32 + self.offset.1)
+  }
+}
+
+/// Placement of every element on an output. Elements without a placement are stacked
+/// in a column below the clock.
+#[derive(Clone, Copy)]
+pub struct Layout {
+  pub clock: Placement,
+  pub indicator: Option<Placement>,
+  pub password: Option<Placement>,
+  pub message: Option<Placement>,
+  pub user: Option<Placement>,
+  pub battery: Placement,
+}
+
+pub fn parse_offset(str: &str) -> Result<(i32, i32), String> {
+  let (x, y) = str.split_once(',').ok_or("expected X,Y")?;
+  let x = x.trim().parse::<i32>().map_err(|e| e.to_string())?;
+  let y = y.trim().parse::<i32>().map_err(|e| e.to_string())?;
+  Ok((x, y))
+}
+
+pub fn parse_position(str: &str) -> Result<(f64, f64), String> {
+  let (x, y) = str.split_once(',').ok_or("expected X%,Y%")?;
+  let parse = |v: &str| {
+    let v = v.trim().trim_end_matches('%');
+    v.parse::<f64>().map_err(|e| e.to_string())
+  };
+  Ok((parse(x)?, parse(y)?))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const OUTPUT: (u32, u32) = (1000, 500);
+  const SIZE: (u32, u32) = (100, 50);
+
+  fn placement(anchor: Anchor, offset: (i32, i32), margin: i32, position: Option<(f64, f64)>) -> Placement {
+    Placement {
+      anchor,
+      offset,
+      margin,
+      position,
+    }
+  }
+
+  #[test]
+  fn places_every_anchor_inside_the_margin() {
+    let cases = [
+      (Anchor::TopLeft, (10, 10)),
+      (Anchor::Top, (450, 10)),
+      (Anchor::TopRight, (890, 10)),
+      (Anchor::Left, (10, 225)),
+      (Anchor::Center, (450, 225)),
+      (Anchor::Right, (890, 225)),
+      (Anchor::BottomLeft, (10, 440)),
+      (Anchor::Bottom, (450, 440)),
+      (Anchor::BottomRight, (890, 440)),
+    ];
+    for (anchor, expected) in cases {
+      assert_eq!(placement(anchor, (0, 0), 10, None).place(OUTPUT, SIZE), expected);
+    }
+  }
+
+  #[test]
+  fn applies_negative_offsets() {
+    let cases = [
+      (Anchor::TopLeft, (-20, -5), (-20, -5)),
+      (Anchor::TopRight, (-20, -5), (880, -5)),
+      (Anchor::BottomLeft, (-30, 15), (-30, 465)),
+      (Anchor::Center, (-450, -225), (0, 0)),
+    ];
+    for (anchor, offset, expected) in cases {
+      assert_eq!(placement(anchor, offset, 0, None).place(OUTPUT, SIZE), expected);
+    }
+  }
+
+  #[test]
+  fn places_at_percentage_positions() {
+    let cases = [
+      // The margin only applies to the output edges
+      (Anchor::Center, (25.0, 75.0), (0, 0), (200, 350)),
+      (Anchor::Center, (25.0, 75.0), (5, -5), (205, 345)),
+      (Anchor::TopLeft, (0.0, 0.0), (0, 0), (0, 0)),
+      (Anchor::BottomRight, (100.0, 100.0), (0, 0), (900, 450)),
+      (Anchor::Top, (33.3, 10.0), (0, 0), (283, 50)),
+    ];
+    for (anchor, position, offset, expected) in cases {
+      let placement = placement(anchor, offset, 10, Some(position));
+      assert_eq!(placement.place(OUTPUT, SIZE), expected);
+    }
+  }
+
+  #[test]
+  fn parses_offsets() {
+    let cases = [
+      ("10,-20", Ok((10, -20))),
+      (" -5 , 7 ", Ok((-5, 7))),
+      ("0,0", Ok((0, 0))),
+    ];
+    for (input, expected) in cases {
+      assert_eq!(parse_offset(input), expected);
+    }
+    for input in ["10", "a,1", "1,2,3", "1.5,2"] {
+      assert!(parse_offset(input).is_err(), "{}", input);
+    }
+  }
+
+  #[test]
+  fn parses_positions() {
+    let cases = [
+      ("25%,75%", Ok((25.0, 75.0))),
+      ("50, 12.5%", Ok((50.0, 12.5))),
+      ("-10%,110%", Ok((-10.0, 110.0))),
+    ];
+    for (input, expected) in cases {
+      assert_eq!(parse_position(input), expected);
+    }
+    for input in ["50%", "x%,1%", "1%,%"] {
+      assert!(parse_position(input).is_err(), "{}", input);
+    }
+  }
+}
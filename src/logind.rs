@@ -0,0 +1,519 @@
+use std::ffi::OsString;
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::{OwnedFd, OwnedObjectPath};
+
+const DESTINATION: &str = "org.freedesktop.login1";
+
+/// Requests from logind forwarded to the event loop
+pub enum LogindEvent {
+  /// The session should be locked, from `loginctl lock-session` and similar
+  Lock,
+  /// The system is about to sleep when true, or has resumed when false
+  PrepareForSleep(bool),
+}
+
+/// Connection to logind for the session minlock runs in
+pub struct Logind {
+  manager: Proxy<'static>,
+  session: Proxy<'static>,
+  inhibitor: Option<OwnedFd>,
+  locked: bool,
+  sleep_pending: bool,
+}
+
+impl Logind {
+  /// Connect to the system bus and forward the signals of the current session to `sender`
+  pub fn connect(sender: calloop::channel::Sender<LogindEvent>) -> zbus::Result<Self> {
+    Self::with_connection(&Connection::system()?, sender)
+  }
+
+  fn with_connection(connection: &Connection, sender: calloop::channel::Sender<LogindEvent>) -> zbus::Result<Self> {
+    let manager = Proxy::new(
+      connection,
+      DESTINATION,
+      "/org/freedesktop/login1",
+      "org.freedesktop.login1.Manager",
+    )?;
+    let session_path: OwnedObjectPath = match std::env::var("XDG_SESSION_ID") {
+      Ok(id) => manager.call("GetSession", &(id,))?,
+      Err(_) => manager.call("GetSessionByPID", &(std::process::id(),))?,
+    };
+    let session = Proxy::new(
+      connection,
+      DESTINATION,
+      session_path.into_inner(),
+      "org.freedesktop.login1.Session",
+    )?;
+
+    // Signals are read on their own threads, the event loop only sees the channel
+    let sleep_signals = manager.receive_signal("PrepareForSleep")?;
+    let sleep_sender = sender.clone();
+    thread::spawn(move || {
+      for message in sleep_signals {
+        if let Ok(start) = message.body::<bool>() {
+          if sleep_sender.send(LogindEvent::PrepareForSleep(start)).is_err() {
+            break;
+          }
+        }
+      }
+    });
+    let lock_signals = session.receive_signal("Lock")?;
+    thread::spawn(move || {
+      for _ in lock_signals {
+        if sender.send(LogindEvent::Lock).is_err() {
+          break;
+        }
+      }
+    });
+
+    let mut logind = Self {
+      manager,
+      session,
+      inhibitor: None,
+      locked: false,
+      sleep_pending: false,
+    };
+    logind.set_locked_hint(false);
+    logind.inhibit()?;
+    Ok(logind)
+  }
+
+  /// Follow a request from logind, returns whether the session should be locked
+  pub fn handle(&mut self, event: LogindEvent) -> bool {
+    match event {
+      LogindEvent::Lock => !self.locked,
+      LogindEvent::PrepareForSleep(true) => {
+        self.sleep_pending = true;
+        if self.locked {
+          self.release();
+        }
+        !self.locked
+      }
+      LogindEvent::PrepareForSleep(false) => {
+        self.sleep_pending = false;
+        self.reinhibit();
+        false
+      }
+    }
+  }
+
+  /// The lock has been confirmed by the compositor, only now is the screen guaranteed to be covered
+  pub fn locked(&mut self) {
+    self.locked = true;
+    self.set_locked_hint(true);
+    self.release();
+    if !self.sleep_pending {
+      self.reinhibit();
+    }
+  }
+
+  /// The lock is gone, either unlocked or never taken
+  pub fn unlocked(&mut self) {
+    self.locked = false;
+    self.set_locked_hint(false);
+    if self.sleep_pending {
+      // Locking failed, do not hold up sleep any longer
+      self.release();
+    }
+  }
+
+  fn reinhibit(&mut self) {
+    if let Err(err) = self.inhibit() {
+      eprintln!("Failed to take the sleep inhibitor: {}", err);
+    }
+  }
+
+  /// Take a delay inhibitor so sleep waits until the session is locked
+  fn inhibit(&mut self) -> zbus::Result<()> {
+    if self.inhibitor.is_none() {
+      let fd: OwnedFd = self
+        .manager
+        .call("Inhibit", &("sleep", "minlock", "Lock the screen before sleep", "delay"))?;
+      self.inhibitor = Some(fd);
+    }
+    Ok(())
+  }
+
+  /// Let a pending sleep go ahead, closing the inhibitor fd releases it
+  fn release(&mut self) {
+    self.inhibitor = None;
+  }
+
+  fn set_locked_hint(&self, locked: bool) {
+    if let Err(err) = self.session.call::<_, _, ()>("SetLockedHint", &(locked,)) {
+      eprintln!("Failed to set the locked hint: {}", err);
+    }
+  }
+}
+
+/// Progress of the lock client spawned by the helper
+enum ChildEvent {
+  Locked,
+  Exited,
+}
+
+struct Helper {
+  logind: Logind,
+  lock_args: Vec<OsString>,
+  child_sender: calloop::channel::Sender<ChildEvent>,
+  running: bool,
+}
+
+impl Helper {
+  fn lock(&mut self) {
+    if self.running {
+      return;
+    }
+    let child = std::env::current_exe().and_then(|exe| {
+      Command::new(exe)
+        .args(&self.lock_args)
+        .arg("--notify-locked")
+        .stdout(Stdio::piped())
+        .spawn()
+    });
+    let mut child = match child {
+      Ok(child) => child,
+      Err(err) => {
+        eprintln!("Failed to start the lock client: {}", err);
+        self.logind.unlocked();
+        return;
+      }
+    };
+    self.running = true;
+    let stdout = child.stdout.take().unwrap();
+    let sender = self.child_sender.clone();
+    thread::spawn(move || {
+      for line in BufReader::new(stdout).lines() {
+        if line.as_ref().is_ok_and(|line| line == "locked") {
+          let _ = sender.send(ChildEvent::Locked);
+        }
+      }
+      let _ = child.wait();
+      let _ = sender.send(ChildEvent::Exited);
+    });
+  }
+
+  fn handle_child(&mut self, event: ChildEvent) {
+    match event {
+      ChildEvent::Locked => self.logind.locked(),
+      ChildEvent::Exited => {
+        self.running = false;
+        self.logind.unlocked();
+      }
+    }
+  }
+}
+
+/// Stay running and spawn a lock client with the same options whenever logind asks for a lock
+pub fn run_helper() {
+  let mut event_loop = calloop::EventLoop::<Helper>::try_new().expect("Failed to initialize event loop");
+  let (logind_sender, logind_channel) = calloop::channel::channel();
+  let (child_sender, child_channel) = calloop::channel::channel();
+  let logind = match Logind::connect(logind_sender) {
+    Ok(logind) => logind,
+    Err(err) => {
+      eprintln!("Failed to connect to logind: {}", err);
+      std::process::exit(1);
+    }
+  };
+
+  event_loop
+    .handle()
+    .insert_source(logind_channel, |event, _, helper| {
+      if let calloop::channel::Event::Msg(event) = event {
+        if helper.logind.handle(event) {
+          helper.lock();
+        }
+      }
+    })
+    .unwrap();
+  event_loop
+    .handle()
+    .insert_source(child_channel, |event, _, helper| {
+      if let calloop::channel::Event::Msg(event) = event {
+        helper.handle_child(event);
+      }
+    })
+    .unwrap();
+
+  let mut helper = Helper {
+    logind,
+    // Passed on unchanged, `--notify-locked` keeps the client from becoming a helper itself
+    lock_args: std::env::args_os().skip(1).collect(),
+    child_sender,
+    running: false,
+  };
+  event_loop
+    .run(Duration::from_secs(60), &mut helper, |_| {})
+    .expect("Error during event loop");
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Read;
+  use std::os::fd::{FromRawFd, IntoRawFd};
+  use std::os::unix::net::UnixStream;
+  use std::process::Child;
+  use std::sync::{Arc, Mutex};
+  use std::time::Instant;
+
+  use zbus::blocking::ConnectionBuilder;
+
+  use super::*;
+
+  const MANAGER_PATH: &str = "/org/freedesktop/login1";
+  const SESSION_PATH: &str = "/org/freedesktop/login1/session/test";
+
+  /// Private bus with a fake logind, the daemon is killed when dropped
+  struct Bus {
+    daemon: Child,
+    dir: std::path::PathBuf,
+    address: String,
+    service: Connection,
+    /// Our end of every inhibitor handed out, reads EOF once the lock client closes its end
+    inhibitors: Arc<Mutex<Vec<UnixStream>>>,
+    hints: Arc<Mutex<Vec<bool>>>,
+  }
+
+  struct FakeManager {
+    inhibitors: Arc<Mutex<Vec<UnixStream>>>,
+  }
+
+  #[zbus::dbus_interface(name = "org.freedesktop.login1.Manager")]
+  impl FakeManager {
+    fn get_session(&self, _id: String) -> OwnedObjectPath {
+      OwnedObjectPath::try_from(SESSION_PATH).unwrap()
+    }
+
+    #[dbus_interface(name = "GetSessionByPID")]
+    fn get_session_by_pid(&self, _pid: u32) -> OwnedObjectPath {
+      OwnedObjectPath::try_from(SESSION_PATH).unwrap()
+    }
+
+    fn inhibit(&self, what: String, _who: String, _why: String, mode: String) -> OwnedFd {
+      assert_eq!((what.as_str(), mode.as_str()), ("sleep", "delay"));
+      let (ours, theirs) = UnixStream::pair().unwrap();
+      ours.set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+      self.inhibitors.lock().unwrap().push(ours);
+      unsafe { OwnedFd::from_raw_fd(theirs.into_raw_fd()) }
+    }
+  }
+
+  struct FakeSession {
+    hints: Arc<Mutex<Vec<bool>>>,
+  }
+
+  #[zbus::dbus_interface(name = "org.freedesktop.login1.Session")]
+  impl FakeSession {
+    fn set_locked_hint(&self, locked: bool) {
+      self.hints.lock().unwrap().push(locked);
+    }
+  }
+
+  impl Bus {
+    /// None when dbus-daemon is not installed
+    fn start(name: &str) -> Option<Self> {
+      let dir = std::env::temp_dir().join(format!("minlock-bus-{}-{}", name, std::process::id()));
+      std::fs::create_dir_all(&dir).unwrap();
+      let config = dir.join("bus.conf");
+      std::fs::write(
+        &config,
+        format!(
+          "<busconfig><type>session</type><listen>unix:dir={}</listen><auth>EXTERNAL</auth>\
+           <policy context=\"default\"><allow send_destination=\"*\"/><allow receive_sender=\"*\"/>\
+           <allow own=\"*\"/></policy></busconfig>",
+          dir.display()
+        ),
+      )
+      .unwrap();
+      let mut daemon = match Command::new("dbus-daemon")
+        .arg(format!("--config-file={}", config.display()))
+        .args(["--nofork", "--print-address"])
+        .stdout(Stdio::piped())
+        .spawn()
+      {
+        Ok(daemon) => daemon,
+        Err(err) => {
+          eprintln!("Skipping, failed to start dbus-daemon: {}", err);
+          return None;
+        }
+      };
+      let mut address = String::new();
+      BufReader::new(daemon.stdout.take().unwrap())
+        .read_line(&mut address)
+        .unwrap();
+      let address = address.trim().to_string();
+
+      let inhibitors = Arc::new(Mutex::new(Vec::new()));
+      let hints = Arc::new(Mutex::new(Vec::new()));
+      let manager = FakeManager {
+        inhibitors: inhibitors.clone(),
+      };
+      let session = FakeSession { hints: hints.clone() };
+      let service = ConnectionBuilder::address(address.as_str())
+        .unwrap()
+        .name(DESTINATION)
+        .unwrap()
+        .serve_at(MANAGER_PATH, manager)
+        .unwrap()
+        .serve_at(SESSION_PATH, session)
+        .unwrap()
+        .build()
+        .unwrap();
+      Some(Self {
+        daemon,
+        dir,
+        address,
+        service,
+        inhibitors,
+        hints,
+      })
+    }
+
+    fn client(&self) -> Connection {
+      ConnectionBuilder::address(self.address.as_str())
+        .unwrap()
+        .build()
+        .unwrap()
+    }
+
+    fn prepare_for_sleep(&self, start: bool) {
+      self
+        .service
+        .emit_signal(
+          None::<()>,
+          MANAGER_PATH,
+          "org.freedesktop.login1.Manager",
+          "PrepareForSleep",
+          &start,
+        )
+        .unwrap();
+    }
+
+    fn lock(&self) {
+      self
+        .service
+        .emit_signal(None::<()>, SESSION_PATH, "org.freedesktop.login1.Session", "Lock", &())
+        .unwrap();
+    }
+
+    /// Whether the inhibitor taken `index`-th is still held by the lock client
+    fn inhibitor_held(&self, index: usize) -> bool {
+      let mut inhibitors = self.inhibitors.lock().unwrap();
+      let mut byte = [0];
+      // Nothing is ever written, so the read either times out while held or hits EOF once closed
+      inhibitors[index].read(&mut byte).is_err()
+    }
+
+    fn inhibitor_count(&self) -> usize {
+      self.inhibitors.lock().unwrap().len()
+    }
+
+    fn hints(&self) -> Vec<bool> {
+      self.hints.lock().unwrap().clone()
+    }
+  }
+
+  impl Drop for Bus {
+    fn drop(&mut self) {
+      let _ = self.daemon.kill();
+      let _ = self.daemon.wait();
+      let _ = std::fs::remove_dir_all(&self.dir);
+    }
+  }
+
+  /// Wait for the next event forwarded by the signal threads
+  fn next_event(event_loop: &mut calloop::EventLoop<'static, Vec<LogindEvent>>) -> LogindEvent {
+    let mut events = Vec::new();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while events.is_empty() {
+      assert!(Instant::now() < deadline, "no event from logind");
+      event_loop.dispatch(Duration::from_millis(50), &mut events).unwrap();
+    }
+    events.remove(0)
+  }
+
+  fn event_loop() -> (
+    calloop::EventLoop<'static, Vec<LogindEvent>>,
+    calloop::channel::Sender<LogindEvent>,
+  ) {
+    let event_loop = calloop::EventLoop::try_new().unwrap();
+    let (sender, channel) = calloop::channel::channel();
+    event_loop
+      .handle()
+      .insert_source(channel, |event, _, events: &mut Vec<LogindEvent>| {
+        if let calloop::channel::Event::Msg(event) = event {
+          events.push(event);
+        }
+      })
+      .unwrap();
+    (event_loop, sender)
+  }
+
+  #[test]
+  fn locks_before_sleep() {
+    let Some(bus) = Bus::start("sleep") else { return };
+    let (mut event_loop, sender) = event_loop();
+    let mut logind = Logind::with_connection(&bus.client(), sender).unwrap();
+    assert_eq!(bus.hints(), vec![false]);
+    assert_eq!(bus.inhibitor_count(), 1);
+
+    bus.prepare_for_sleep(true);
+    let event = next_event(&mut event_loop);
+    assert!(matches!(event, LogindEvent::PrepareForSleep(true)));
+    assert!(logind.handle(event));
+    // Sleep must wait until the compositor confirms the lock
+    assert!(bus.inhibitor_held(0));
+
+    logind.locked();
+    assert_eq!(bus.hints(), vec![false, true]);
+    assert!(!bus.inhibitor_held(0));
+    assert_eq!(bus.inhibitor_count(), 1);
+
+    bus.prepare_for_sleep(false);
+    let event = next_event(&mut event_loop);
+    assert!(!logind.handle(event));
+    assert_eq!(bus.inhibitor_count(), 2);
+    assert!(bus.inhibitor_held(1));
+
+    logind.unlocked();
+    assert_eq!(bus.hints(), vec![false, true, false]);
+    assert!(bus.inhibitor_held(1));
+  }
+
+  #[test]
+  fn lock_signal() {
+    let Some(bus) = Bus::start("lock") else { return };
+    let (mut event_loop, sender) = event_loop();
+    let mut logind = Logind::with_connection(&bus.client(), sender).unwrap();
+
+    bus.lock();
+    let event = next_event(&mut event_loop);
+    assert!(matches!(event, LogindEvent::Lock));
+    assert!(logind.handle(event));
+    logind.locked();
+    // Not sleeping, so the inhibitor is taken again straight away for the next sleep
+    assert!(!bus.inhibitor_held(0));
+    assert!(bus.inhibitor_held(1));
+
+    bus.lock();
+    assert!(!logind.handle(next_event(&mut event_loop)));
+  }
+
+  #[test]
+  fn failed_lock_releases_sleep() {
+    let Some(bus) = Bus::start("failed") else { return };
+    let (mut event_loop, sender) = event_loop();
+    let mut logind = Logind::with_connection(&bus.client(), sender).unwrap();
+
+    bus.prepare_for_sleep(true);
+    assert!(logind.handle(next_event(&mut event_loop)));
+    logind.unlocked();
+    assert!(!bus.inhibitor_held(0));
+  }
+}
@@ -1,45 +1,83 @@
+mod animation;
 mod application;
 mod args;
 mod auth;
+mod config;
+mod control;
+mod layout;
+#[cfg(feature = "logind")]
+mod logind;
 mod render;
+mod screencopy;
 mod seat;
 mod shm;
 mod surface;
 mod output;
+mod widget;
 
+use calloop::signals::{Signal, Signals};
 use calloop_wayland_source::WaylandSource;
-use clap::Parser;
-use output::AppOutput;
 use seat::{AppSeat, DispatchKeyEvents};
-use std::sync::{Arc, Mutex};
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::time::Duration;
 use wayland_client::globals::{registry_queue_init, GlobalListContents};
-use wayland_client::protocol::{wl_compositor, wl_output, wl_registry, wl_seat, wl_shm, wl_subcompositor, wl_surface};
-use wayland_client::{delegate_noop, Connection, Dispatch, Proxy, QueueHandle};
+use wayland_client::protocol::{
+  wl_callback, wl_compositor, wl_output, wl_registry, wl_seat, wl_shm, wl_subcompositor, wl_surface,
+};
+use wayland_client::{delegate_noop, Connection, Dispatch, Proxy, QueueHandle, WEnum};
+use wayland_protocols::ext::idle_notify::v1::client::{ext_idle_notification_v1, ext_idle_notifier_v1};
 use wayland_protocols::ext::session_lock::v1::client::{
   ext_session_lock_manager_v1, ext_session_lock_surface_v1, ext_session_lock_v1,
 };
+use wayland_protocols_wlr::output_power_management::v1::client::{
+  zwlr_output_power_manager_v1, zwlr_output_power_v1,
+};
 use xkbcommon::xkb::keysyms;
 
 use crate::application::{AppState, Application};
 use crate::args::Args;
-use crate::surface::AppSurface;
-
-struct AppProcess {
-  running: bool,
-  locked: bool,
-}
+use crate::shm::slot::BufferSlotPool;
 
 delegate_noop!(Application: wl_compositor::WlCompositor);
 delegate_noop!(Application: wl_subcompositor::WlSubcompositor);
-delegate_noop!(Application: ignore wl_shm::WlShm); // Ignore advertise format events
 delegate_noop!(Application: ext_session_lock_manager_v1::ExtSessionLockManagerV1);
+delegate_noop!(Application: zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1);
+delegate_noop!(Application: ext_idle_notifier_v1::ExtIdleNotifierV1);
 delegate_dispatch_seat!(Application);
 delegate_dispatch_surface!(Application);
 delegate_dispatch_output!(Application);
 
 fn main() {
-  let args = Args::parse();
+  let args = Args::load();
+
+  // Lock clients started by the helper see `logind` too, from its arguments or the config file
+  #[cfg(feature = "logind")]
+  if args.runs_logind_helper() {
+    logind::run_helper();
+    return;
+  }
+
+  let mut main_loop = calloop::EventLoop::<'static, Application>::try_new().expect("Failed to initialize event loop");
+  // Signals are blocked before any thread is spawned so only the event loop sees them
+  let mut handled = vec![Signal::SIGUSR1, Signal::SIGTERM, Signal::SIGINT, Signal::SIGHUP];
+  if args.daemon {
+    handled.push(Signal::SIGUSR2);
+  }
+  let signals = Signals::new(&handled).expect("Failed to install signal handlers");
+  let loop_signal = main_loop.get_signal();
+  main_loop
+    .handle()
+    .insert_source(signals, move |event, _, app| match event.signal() {
+      Signal::SIGUSR1 => app.unlock(),
+      Signal::SIGUSR2 => app.lock(),
+      Signal::SIGHUP => app.reload_config(),
+      _ => {
+        // Exit without unlocking, the compositor keeps the session locked once the client is gone
+        loop_signal.stop();
+      }
+    })
+    .unwrap();
 
   let connection = Connection::connect_to_env().unwrap();
   let (globals, wl_queue) = registry_queue_init::<Application>(&connection).unwrap();
@@ -52,100 +90,156 @@ fn main() {
   let wl_seat: wl_seat::WlSeat = globals.bind(&qh, 7..=7, ()).unwrap();
   let ext_session_lock_mgr: ext_session_lock_manager_v1::ExtSessionLockManagerV1 =
     globals.bind(&qh, 1..=1, ()).unwrap();
+  // Output power management is only needed to blank outputs
+  let output_power_manager: Option<zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1> = match args.blank_timeout {
+    Some(_) => globals
+      .bind(&qh, 1..=1, ())
+      .map_err(|err| eprintln!("Outputs cannot be blanked: {}", err))
+      .ok(),
+    None => None,
+  };
 
-  let process = Arc::new(Mutex::new(AppProcess {
-    running: true,
-    locked: false,
-  }));
-
-  // Request lock
-  let ext_session_lock = ext_session_lock_mgr.lock(&qh, Arc::clone(&process));
-  connection.roundtrip().unwrap(); // In case finished event sent by compositor
-
-  // Bind keyboard events
-  let seat = AppSeat::from(&qh, wl_seat);
-
-  // Create surface for each output
-  let outputs = globals
+  // Bind outputs
+  let wl_outputs: Vec<(u32, wl_output::WlOutput)> = globals
     .contents()
     .clone_list()
     .iter()
-    .filter_map(|global| {
-      if global.interface == wl_output::WlOutput::interface().name {
-        let wl_output: wl_output::WlOutput = globals.bind(&qh, 4..=4, ()).unwrap();
-        let surface = AppSurface::create(&qh, &wl_shm, &wl_compositor, &wl_subcompositor);
-        let ext_session_lock_surface = ext_session_lock.get_lock_surface(surface.as_ref(), &wl_output.clone(), &qh, surface.as_ref().clone());
-        let output = AppOutput::new(wl_output, ext_session_lock_surface, surface);
-        Some(output)
-      } else {
-        None
-      }
+    .filter(|global| global.interface == wl_output::WlOutput::interface().name)
+    .map(|global| {
+      if global.version < 4 { panic!("require wl_output version 4 or higher") }
+      (global.name, globals.registry().bind(global.name, 4, &qh, ()))
     })
     .collect();
 
-  let mut main_loop = calloop::EventLoop::<'static, Application>::try_new().expect("Failed to initialize event loop");
+  // Idle notifications are only needed to dim the lock screen
+  let idle_notifier: Option<ext_idle_notifier_v1::ExtIdleNotifierV1> = match args.dim_timeout {
+    Some(_) => globals
+      .bind(&qh, 1..=1, ())
+      .map_err(|err| eprintln!("The lock screen cannot be dimmed: {}", err))
+      .ok(),
+    None => None,
+  };
+  let idle_notifier = idle_notifier.map(|notifier| (notifier, wl_seat.clone()));
+
+  // Bind keyboard events
+  let seat = AppSeat::from(&qh, wl_seat);
+
+  // Surfaces of every output draw into one pool
+  let shm_cap = args.shm_cap as usize * 1024 * 1024;
+  let shm_pool = BufferSlotPool::create(4096, shm_cap, &wl_shm).expect("Failed to create shared memory");
+  let shm_pool = Rc::new(RefCell::new(shm_pool));
 
   let mut app = Application::new(
     args,
+    connection.clone(),
+    globals,
     main_loop.handle(),
+    qh.clone(),
     seat,
-    outputs,
+    wl_outputs,
+    shm_pool,
     wl_shm,
     wl_compositor,
     wl_subcompositor,
-    ext_session_lock.clone());
+    ext_session_lock_mgr,
+    output_power_manager,
+    idle_notifier);
+
+  let mut control_socket = None;
+  if app.args.daemon {
+    match app.args.control_socket.clone().or_else(control::default_path) {
+      Some(path) => match control::listen(&path, &main_loop.handle()) {
+        Ok(()) => control_socket = Some(path),
+        Err(err) => eprintln!("Failed to listen on {}: {}", path.display(), err),
+      },
+      None => eprintln!("XDG_RUNTIME_DIR is not set, pass --control-socket to listen for commands"),
+    }
+    #[cfg(feature = "logind")]
+    if app.args.logind {
+      let (sender, channel) = calloop::channel::channel();
+      match logind::Logind::connect(sender) {
+        Ok(logind) => {
+          app.logind = Some(logind);
+          main_loop
+            .handle()
+            .insert_source(channel, |event, _, app| {
+              if let calloop::channel::Event::Msg(event) = event {
+                if app.logind.as_mut().is_some_and(|logind| logind.handle(event)) {
+                  app.lock();
+                }
+              }
+            })
+            .unwrap();
+        }
+        Err(err) => eprintln!("Failed to connect to logind: {}", err),
+      }
+    }
+  } else {
+    app.lock();
+    connection.roundtrip().unwrap(); // In case finished event sent by compositor
+  }
+
+  // Reload whenever the config file changes, including when it is only created later
+  let config_path = app.args.config_path();
+  if let Some(path) = config_path.filter(|path| path.parent().is_none_or(|dir| dir.as_os_str().is_empty() || dir.is_dir())) {
+    if let Err(err) = config::watch(&path, &main_loop.handle()) {
+      eprintln!("Failed to watch {}: {}", path.display(), err);
+    }
+  }
 
   // Wayland event queue
   let wayland_source = WaylandSource::new(connection.clone(), wl_queue);
   wayland_source.insert(main_loop.handle()).unwrap();
 
-  // Periodic clock redraw
+  // Periodic widget redraw
   main_loop
     .handle()
     .insert_source(calloop::timer::Timer::immediate(), |event, _metadata, app| {
-      for output in app.outputs.iter_mut() {
-        output.surface.render_clock(
-          app.args.clock_color,
-          app.args.clock_font.clone(),
-          app.args.clock_font_size,
-          app.args.bg_color,
-        );
-      }
-      calloop::timer::TimeoutAction::ToInstant(event + Duration::from_secs(1))
+      let next = app.render_due().unwrap_or(event + Duration::from_secs(1));
+      calloop::timer::TimeoutAction::ToInstant(next)
     })
     .unwrap();
 
   let signal = main_loop.get_signal();
   main_loop
     .run(Duration::from_secs(1), &mut app, |app| {
-      let process = Arc::clone(&process);
-      let mut process = process.lock().unwrap();
-
-      // Exit once authenticated
-      if matches!(app.current_state(), AppState::Success) {
-        process.running = false;
+      // Unlock once authenticated and faded out
+      if app.ready_to_unlock() {
+        app.unlock();
       }
-      // Destroy lock
-      if !process.running {
-        if process.locked {
-          ext_session_lock.unlock_and_destroy();
-        } else {
-          ext_session_lock.destroy();
-        }
-        connection.flush().unwrap();
+      // Only the daemon outlives its lock
+      if !app.args.daemon && !app.is_locking() {
         signal.stop();
       }
       connection.flush().unwrap();
     })
     .expect("Error during event loop");
+  // Make sure the unlock is processed before exiting
+  let _ = connection.roundtrip();
+  if let Some(path) = control_socket {
+    let _ = std::fs::remove_file(path);
+  }
 }
 
 impl DispatchKeyEvents for Application {
   fn event(app: &mut Self, keysym: xkbcommon::xkb::Keysym, codepoint: u32) {
+    if app.grace_unlock() {
+      return;
+    }
+    if app.wake() {
+      // The key that turns the outputs back on is not part of the password
+      return;
+    }
     if matches!(app.current_state(), AppState::Verifying) {
       // Block key events when verifying
       return;
     }
+    if let Some(chord) = app.args.password_reveal_key.as_ref() {
+      if chord.matches_key(keysym) && app.seat.modifiers_active(&chord.modifiers) {
+        app.set_password_revealed(true);
+        return;
+      }
+    }
     match keysym {
       keysyms::KEY_Escape => {
         app.password_clear();
@@ -166,6 +260,26 @@ impl DispatchKeyEvents for Application {
       }
     }
   }
+
+  fn release(app: &mut Self, keysym: xkbcommon::xkb::Keysym) {
+    if let Some(chord) = app.args.password_reveal_key.as_ref() {
+      if chord.matches_key(keysym) {
+        app.set_password_revealed(false);
+      }
+    }
+  }
+
+  fn leave(app: &mut Self) {
+    // Never leave the password revealed without keyboard focus
+    app.set_password_revealed(false);
+  }
+
+  fn pointer(app: &mut Self, motion: bool) {
+    if !(motion && app.args.grace_ignore_pointer) && app.grace_unlock() {
+      return;
+    }
+    app.wake();
+  }
 }
 
 impl Dispatch<ext_session_lock_surface_v1::ExtSessionLockSurfaceV1, wl_surface::WlSurface> for Application {
@@ -179,41 +293,87 @@ impl Dispatch<ext_session_lock_surface_v1::ExtSessionLockSurfaceV1, wl_surface::
   ) {
     if let ext_session_lock_surface_v1::Event::Configure { serial, width, height } = event {
       proxy.ack_configure(serial);
-      let surface = app
-        .outputs
-        .iter_mut()
-        .map(|o| &mut o.surface)
-        .find(|surface| surface.as_ref().id() == data.id());
-      if let Some(surface) = surface {
-        surface.set_dimensions(width, height);
-        surface.render_bg(app.args.bg_color);
-        surface.render_clock(
-          app.args.clock_color,
-          app.args.clock_font.clone(),
-          app.args.clock_font_size,
-          app.args.bg_color,
-        );
-        surface.render_indicator_full(app.args.indicator_idle_color, app.args.bg_color);
-        surface.as_ref().commit();
-      }
+      app.configure_surface(data, width, height);
     }
   }
 }
 
-impl Dispatch<ext_session_lock_v1::ExtSessionLockV1, Arc<Mutex<AppProcess>>> for Application {
+impl Dispatch<ext_session_lock_v1::ExtSessionLockV1, ()> for Application {
   fn event(
-    _app: &mut Self,
+    app: &mut Self,
     _proxy: &ext_session_lock_v1::ExtSessionLockV1,
     event: <ext_session_lock_v1::ExtSessionLockV1 as Proxy>::Event,
-    data: &Arc<Mutex<AppProcess>>,
+    _data: &(),
     _conn: &Connection,
     _qhandle: &QueueHandle<Self>,
   ) {
-    let mut process = data.lock().unwrap();
     if let ext_session_lock_v1::Event::Finished = event {
-      process.running = false;
+      // Destroys the lock, unlocking first if it was confirmed as the protocol asks
+      app.unlock();
     } else if let ext_session_lock_v1::Event::Locked = event {
-      process.locked = true;
+      app.set_locked();
+    }
+  }
+}
+
+impl Dispatch<wl_callback::WlCallback, wl_surface::WlSurface> for Application {
+  fn event(
+    app: &mut Self,
+    _proxy: &wl_callback::WlCallback,
+    event: <wl_callback::WlCallback as Proxy>::Event,
+    data: &wl_surface::WlSurface,
+    _conn: &Connection,
+    _qhandle: &QueueHandle<Self>,
+  ) {
+    if let wl_callback::Event::Done { .. } = event {
+      app.frame_done(data);
+    }
+  }
+}
+
+impl Dispatch<zwlr_output_power_v1::ZwlrOutputPowerV1, ()> for Application {
+  fn event(
+    app: &mut Self,
+    proxy: &zwlr_output_power_v1::ZwlrOutputPowerV1,
+    event: <zwlr_output_power_v1::ZwlrOutputPowerV1 as Proxy>::Event,
+    _data: &(),
+    _conn: &Connection,
+    _qhandle: &QueueHandle<Self>,
+  ) {
+    if let zwlr_output_power_v1::Event::Failed = event {
+      app.output_power_failed(proxy);
+    }
+  }
+}
+
+impl Dispatch<ext_idle_notification_v1::ExtIdleNotificationV1, ()> for Application {
+  fn event(
+    app: &mut Self,
+    _proxy: &ext_idle_notification_v1::ExtIdleNotificationV1,
+    event: <ext_idle_notification_v1::ExtIdleNotificationV1 as Proxy>::Event,
+    _data: &(),
+    _conn: &Connection,
+    _qhandle: &QueueHandle<Self>,
+  ) {
+    match event {
+      ext_idle_notification_v1::Event::Idled => app.set_idle(true),
+      ext_idle_notification_v1::Event::Resumed => app.set_idle(false),
+      _ => {}
+    }
+  }
+}
+
+impl Dispatch<wl_shm::WlShm, ()> for Application {
+  fn event(
+    state: &mut Self,
+    _proxy: &wl_shm::WlShm,
+    event: <wl_shm::WlShm as Proxy>::Event,
+    _data: &(),
+    _conn: &Connection,
+    _qhandle: &QueueHandle<Self>,
+  ) {
+    if let wl_shm::Event::Format { format: WEnum::Value(format) } = event {
+      state.shm_formats.push(format);
     }
   }
 }
@@ -231,13 +391,10 @@ impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for Application {
       if interface == wl_output::WlOutput::interface().name {
         if version < 4 { panic!("require wl_output version 4 or higher") }
         let wl_output = registry.bind(name, version, qhandle, ());
-        let surface = AppSurface::create(qhandle, &state.wl_shm, &state.wl_compositor, &state.wl_subcompositor);
-        let ext_session_lock_surface = state.ext_session_lock.get_lock_surface(&surface.as_ref().clone(), &wl_output, qhandle, surface.as_ref().clone());
-        let output = AppOutput::new(wl_output.clone(), ext_session_lock_surface, surface);
-        state.outputs.push(output);
+        state.add_output(name, wl_output);
       }
     } else if let wl_registry::Event::GlobalRemove { name } = event {
-      state.outputs.retain(|output| output.as_ref().id().protocol_id() != name);
+      state.remove_output(name);
     }
   }
 }
\ No newline at end of file
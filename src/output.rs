@@ -1,31 +1,57 @@
 use wayland_client::protocol::wl_output;
 use wayland_protocols::ext::session_lock::v1::client::ext_session_lock_surface_v1;
+use wayland_protocols_wlr::output_power_management::v1::client::zwlr_output_power_v1;
 
 use crate::surface::AppSurface;
 
 pub struct AppOutput {
   pub wl_output: wl_output::WlOutput,
   pub ext_session_lock_surface: ext_session_lock_surface_v1::ExtSessionLockSurfaceV1,
-  pub surface: AppSurface
+  pub surface: AppSurface,
+  pub power: Option<zwlr_output_power_v1::ZwlrOutputPowerV1>,
+  powered_off: bool,
 }
 
 impl AppOutput {
   pub fn new(
     wl_output: wl_output::WlOutput,
     ext_session_lock_surface: ext_session_lock_surface_v1::ExtSessionLockSurfaceV1,
-    surface: AppSurface) -> AppOutput {
+    surface: AppSurface,
+    power: Option<zwlr_output_power_v1::ZwlrOutputPowerV1>) -> AppOutput {
       AppOutput {
         wl_output,
         ext_session_lock_surface,
-        surface
+        surface,
+        power,
+        powered_off: false
       }
   }
+
+  /// Turn the output on or off, does nothing without output power management
+  pub fn set_power(&mut self, on: bool) {
+    let mode = if on {
+      zwlr_output_power_v1::Mode::On
+    } else {
+      zwlr_output_power_v1::Mode::Off
+    };
+    if let Some(power) = self.power.as_ref() {
+      power.set_mode(mode);
+      self.powered_off = !on;
+    }
+  }
 }
 
 impl Drop for AppOutput {
   fn drop(&mut self) {
+    if let Some(power) = self.power.take() {
+      // Never leave an output dark that was turned off while locked
+      if self.powered_off {
+        power.set_mode(zwlr_output_power_v1::Mode::On);
+      }
+      power.destroy();
+    }
+    // The wl_output outlives the lock and is released once its global is removed
     self.ext_session_lock_surface.destroy();
-    self.wl_output.release();
   }
 }
 
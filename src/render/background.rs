@@ -1,22 +1,203 @@
+use std::f64::consts::PI;
+
 use crate::{
-  args::Color,
-  shm::slot::{BufferSlot, BufferSlotPool},
+  args::{parse_color, Color},
+  render::buffer_context,
+  render::effects::{Effect, ImageData},
+  shm::slot::BufferSlot,
 };
 
-pub fn draw_background(pool: &mut BufferSlotPool, width: u32, height: u32, color: Color) -> &mut BufferSlot {
-  let (buffer, data) = pool.get_next_buffer(width, height);
-  let surface = unsafe {
-    cairo::ImageSurface::create_for_data_unsafe(
-      data.first_mut().unwrap(),
-      cairo::Format::ARgb32,
-      buffer.width().try_into().unwrap(),
-      buffer.height().try_into().unwrap(),
-      buffer.stride().try_into().unwrap(),
-    )
-    .unwrap()
+#[derive(Clone)]
+pub enum GradientKind {
+  /// Angle in degrees, clockwise with 0 running from left to right
+  Linear(f64),
+  Radial,
+}
+
+#[derive(Clone)]
+pub struct Gradient {
+  pub kind: GradientKind,
+  pub stops: Vec<(f64, Color)>,
+}
+
+#[derive(Clone, Copy)]
+pub enum Pattern {
+  Noise(f64),
+  Checker(u32, Color, Color),
+  Stripes(u32, f64, Color, Color),
+}
+
+/// Everything drawn on the base surface, in drawing order
+#[derive(Clone)]
+pub struct Background {
+  pub color: Color,
+  pub gradient: Option<Gradient>,
+  pub pattern: Option<Pattern>,
+  pub effects: Vec<Effect>,
+}
+
+impl Background {
+  /// Whether drawing is expensive enough for the result to be kept per output size
+  pub fn is_cached(&self) -> bool {
+    self.gradient.is_some() || self.pattern.is_some() || !self.effects.is_empty()
+  }
+
+  /// Whether 10 bit channels help, only gradients band and the effects work on 8 bit channels
+  pub fn wants_deep_color(&self) -> bool {
+    self.gradient.is_some() && self.effects.is_empty() && !matches!(self.pattern, Some(Pattern::Noise(_)))
+  }
+}
+
+/// Parse `linear:<angle>:<color>[@<pos>],...` or `radial:<color>[@<pos>],...`, positions are
+/// between 0 and 1 and spread evenly when left out
+pub fn parse_gradient(str: &str) -> Result<Gradient, String> {
+  let (kind, stops) = match str.split_once(':') {
+    Some(("linear", rest)) => {
+      let (angle, stops) = rest.split_once(':').ok_or("expected linear:<angle>:<stops>")?;
+      let angle = angle.parse::<f64>().map_err(|e| e.to_string())?;
+      (GradientKind::Linear(angle), stops)
+    }
+    Some(("radial", stops)) => (GradientKind::Radial, stops),
+    _ => return Err("expected linear or radial gradient".to_owned()),
   };
-  let context = cairo::Context::new(&surface).unwrap();
+  let stops: Vec<&str> = stops.split(',').collect();
+  if stops.len() < 2 {
+    return Err("a gradient needs at least two stops".to_owned());
+  }
+  let stops = stops
+    .iter()
+    .enumerate()
+    .map(|(i, stop)| {
+      let (color, position) = match stop.split_once('@') {
+        Some((color, position)) => (color, position.parse::<f64>().map_err(|e| e.to_string())?),
+        None => (*stop, i as f64 / (stops.len() - 1) as f64),
+      };
+      Ok((position, parse_color(color).map_err(|e| e.to_string())?))
+    })
+    .collect::<Result<Vec<_>, String>>()?;
+  Ok(Gradient { kind, stops })
+}
+
+/// Parse `noise:<amount>`, `checker:<size>:<color>:<color>` or `stripes:<width>:<angle>:<color>:<color>`
+pub fn parse_pattern(str: &str) -> Result<Pattern, String> {
+  let parts: Vec<&str> = str.split(':').collect();
+  let number = |v: &str| v.parse::<f64>().map_err(|e| e.to_string());
+  let size = |v: &str| v.parse::<u32>().map_err(|e| e.to_string()).and_then(|v| match v {
+    0 => Err("size must be positive".to_owned()),
+    v => Ok(v),
+  });
+  let color = |v: &str| parse_color(v).map_err(|e| e.to_string());
+  match parts.as_slice() {
+    ["noise", amount] => Ok(Pattern::Noise(number(amount)?)),
+    ["checker", s, a, b] => Ok(Pattern::Checker(size(s)?, color(a)?, color(b)?)),
+    ["stripes", s, angle, a, b] => Ok(Pattern::Stripes(size(s)?, number(angle)?, color(a)?, color(b)?)),
+    _ => Err("expected noise:<amount>, checker:<size>:<color>:<color> or stripes:<width>:<angle>:<color>:<color>".to_owned()),
+  }
+}
+
+pub fn draw_background(
+  buffer: &BufferSlot,
+  data: &mut [u8],
+  background: &Background,
+  image: Option<&cairo::ImageSurface>,
+) {
+  let (width, height) = (buffer.width() as f64, buffer.height() as f64);
+  let context = buffer_context(buffer, data);
+  // The lock surface is opaque, so the base colour ignores alpha while the layers above blend onto it
+  let color = background.color;
   context.set_source_rgb(color.r, color.g, color.b);
   context.paint().unwrap();
-  buffer
+  if let Some(gradient) = background.gradient.as_ref() {
+    draw_gradient(&context, gradient, width, height);
+  }
+  if let Some(pattern) = background.pattern {
+    draw_pattern(&context, pattern, width, height);
+  }
+  if let Some(image) = image {
+    // Scale to cover the whole output, cropping around the centre
+    let (image_width, image_height) = (image.width() as f64, image.height() as f64);
+    let scale = f64::max(width / image_width, height / image_height);
+    context.save().unwrap();
+    context.translate((width - image_width * scale) / 2.0, (height - image_height * scale) / 2.0);
+    context.scale(scale, scale);
+    context.set_source_surface(image, 0.0, 0.0).unwrap();
+    context.source().set_filter(cairo::Filter::Good);
+    context.paint().unwrap();
+    context.restore().unwrap();
+  }
+  drop(context);
+  if let Some(Pattern::Noise(amount)) = background.pattern {
+    let mut image = ImageData {
+      data,
+      width: buffer.width() as usize,
+      height: buffer.height() as usize,
+      stride: buffer.stride() as usize,
+    };
+    image.noise(amount);
+  }
+}
+
+fn draw_gradient(context: &cairo::Context, gradient: &Gradient, width: f64, height: f64) {
+  let (cx, cy) = (width / 2.0, height / 2.0);
+  let add_stops = |pattern: &cairo::Gradient| {
+    for (position, color) in gradient.stops.iter() {
+      pattern.add_color_stop_rgba(*position, color.r, color.g, color.b, color.a);
+    }
+  };
+  match gradient.kind {
+    GradientKind::Linear(angle) => {
+      // Gradient line through the centre, long enough for the stops to reach the corners
+      let angle = angle * PI / 180.0;
+      let (dx, dy) = (angle.cos(), angle.sin());
+      let half = (width * dx.abs() + height * dy.abs()) / 2.0;
+      let source = cairo::LinearGradient::new(cx - dx * half, cy - dy * half, cx + dx * half, cy + dy * half);
+      add_stops(&source);
+      context.set_source(&source).unwrap();
+    }
+    GradientKind::Radial => {
+      let radius = (cx * cx + cy * cy).sqrt();
+      let source = cairo::RadialGradient::new(cx, cy, 0.0, cx, cy, radius);
+      add_stops(&source);
+      context.set_source(&source).unwrap();
+    }
+  }
+  context.paint().unwrap();
+}
+
+fn draw_pattern(context: &cairo::Context, pattern: Pattern, width: f64, height: f64) {
+  match pattern {
+    Pattern::Checker(size, a, b) => {
+      let size = size as f64;
+      // Tile of two by two squares repeated over the output
+      let tile = cairo::ImageSurface::create(cairo::Format::ARgb32, (size * 2.0) as i32, (size * 2.0) as i32).unwrap();
+      let tile_context = cairo::Context::new(&tile).unwrap();
+      tile_context.set_source_rgba(a.r, a.g, a.b, a.a);
+      tile_context.paint().unwrap();
+      tile_context.set_source_rgba(b.r, b.g, b.b, b.a);
+      tile_context.rectangle(size, 0.0, size, size);
+      tile_context.rectangle(0.0, size, size, size);
+      tile_context.fill().unwrap();
+      drop(tile_context);
+      let source = cairo::SurfacePattern::create(&tile);
+      source.set_extend(cairo::Extend::Repeat);
+      source.set_filter(cairo::Filter::Nearest);
+      context.set_source(&source).unwrap();
+    }
+    Pattern::Stripes(size, angle, a, b) => {
+      // Hard stops on a repeating gradient across the stripe direction
+      let size = size as f64;
+      let angle = angle * PI / 180.0;
+      let (dx, dy) = (angle.cos() * size * 2.0, angle.sin() * size * 2.0);
+      let source = cairo::LinearGradient::new(width / 2.0, height / 2.0, width / 2.0 + dx, height / 2.0 + dy);
+      source.add_color_stop_rgba(0.0, a.r, a.g, a.b, a.a);
+      source.add_color_stop_rgba(0.5, a.r, a.g, a.b, a.a);
+      source.add_color_stop_rgba(0.5, b.r, b.g, b.b, b.a);
+      source.add_color_stop_rgba(1.0, b.r, b.g, b.b, b.a);
+      source.set_extend(cairo::Extend::Repeat);
+      context.set_source(&source).unwrap();
+    }
+    // Noise is added to the finished pixels
+    Pattern::Noise(_) => return,
+  }
+  context.paint().unwrap();
 }
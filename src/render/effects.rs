@@ -0,0 +1,421 @@
+use clap::ValueEnum;
+
+use crate::args::{parse_color, Color};
+
+/// Pixel data in cairo's native-endian ARGB32 layout
+pub struct ImageData<'a> {
+  pub data: &'a mut [u8],
+  pub width: usize,
+  pub height: usize,
+  pub stride: usize,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum BlurKind {
+  Box,
+  Gaussian,
+}
+
+#[derive(Clone, Copy)]
+pub enum Effect {
+  Blur(BlurKind, usize),
+  Pixelate(usize),
+  Greyscale,
+  Brightness(f64),
+  Contrast(f64),
+  Overlay(Color, f64),
+  Vignette(f64),
+}
+
+/// Parse an effect such as `blur:8`, `box-blur:4`, `pixelate:12`, `greyscale`, `brightness:-0.2`,
+/// `contrast:1.3`, `overlay:RRGGBB:0.4` or `vignette:0.6`
+pub fn parse_effect(str: &str) -> Result<Effect, String> {
+  let mut parts = str.split(':');
+  let name = parts.next().unwrap_or_default();
+  let mut param = || parts.next().ok_or(format!("missing parameter for '{}'", name));
+  let number = |v: &str| v.parse::<f64>().map_err(|e| e.to_string());
+  let size = |v: &str| v.parse::<usize>().map_err(|e| e.to_string());
+  let effect = match name {
+    "blur" => Effect::Blur(BlurKind::Gaussian, size(param()?)?),
+    "box-blur" => Effect::Blur(BlurKind::Box, size(param()?)?),
+    "pixelate" => Effect::Pixelate(size(param()?)?),
+    "greyscale" | "grayscale" => Effect::Greyscale,
+    "brightness" => Effect::Brightness(number(param()?)?),
+    "contrast" => Effect::Contrast(number(param()?)?),
+    "overlay" => {
+      let color = parse_color(param()?).map_err(|e| e.to_string())?;
+      Effect::Overlay(color, number(param()?)?)
+    }
+    "vignette" => Effect::Vignette(number(param()?)?),
+    _ => return Err(format!("unknown effect '{}'", name)),
+  };
+  Ok(effect)
+}
+
+impl ImageData<'_> {
+  pub fn apply(&mut self, effect: Effect) {
+    match effect {
+      Effect::Blur(kind, radius) => self.blur(kind, radius),
+      Effect::Pixelate(size) => self.pixelate(size),
+      Effect::Greyscale => self.greyscale(),
+      Effect::Brightness(amount) => self.brightness(amount),
+      Effect::Contrast(factor) => self.contrast(factor),
+      Effect::Overlay(color, amount) => self.overlay(color, amount),
+      Effect::Vignette(strength) => self.vignette(strength),
+    }
+  }
+
+  /// Rows handed to each thread so every core gets one chunk
+  fn rows_per_chunk(&self) -> usize {
+    std::cmp::max(self.height.div_ceil(threads()), 1)
+  }
+
+  /// Run `f` on chunks of `rows` rows in parallel, passing the index of the first row
+  fn for_each_chunk(&mut self, rows: usize, f: impl Fn(usize, &mut [u8]) + Sync) {
+    if self.width == 0 || self.height == 0 {
+      return;
+    }
+    let stride = self.stride;
+    let data = &mut self.data[..stride * self.height];
+    std::thread::scope(|scope| {
+      for (i, chunk) in data.chunks_mut(rows * stride).enumerate() {
+        let f = &f;
+        scope.spawn(move || f(i * rows, chunk));
+      }
+    });
+  }
+
+  /// Run `f` on every row in parallel, passing the row index and its pixels
+  fn for_each_row(&mut self, f: impl Fn(usize, &mut [u8]) + Sync) {
+    let (width, stride) = (self.width, self.stride);
+    self.for_each_chunk(self.rows_per_chunk(), |first, chunk| {
+      for (i, row) in chunk.chunks_mut(stride).enumerate() {
+        f(first + i, &mut row[..width * 4]);
+      }
+    });
+  }
+
+  fn for_each_pixel(&mut self, f: impl Fn(&mut [u8]) + Sync) {
+    self.for_each_row(|_, row| row.chunks_exact_mut(4).for_each(&f));
+  }
+
+  pub fn blur(&mut self, kind: BlurKind, radius: usize) {
+    match kind {
+      BlurKind::Box => self.box_blur(radius),
+      BlurKind::Gaussian => {
+        // Three box passes approximate a gaussian with a standard deviation of `radius`
+        let sigma = radius as f64;
+        let box_width = (4.0 * sigma * sigma + 1.0).sqrt();
+        let box_radius = ((box_width - 1.0) / 2.0).round() as usize;
+        for _ in 0..3 {
+          self.box_blur(box_radius);
+        }
+      }
+    }
+  }
+
+  pub fn box_blur(&mut self, radius: usize) {
+    if radius == 0 || self.width == 0 || self.height == 0 {
+      return;
+    }
+    self.blur_rows(radius);
+    // Blur the columns as rows of the transposed image so they can be split across threads too
+    let mut transposed = vec![0u8; self.width * self.height * 4];
+    transpose(self.data, self.stride, &mut transposed, self.height * 4, self.width, self.height);
+    let mut columns = ImageData {
+      data: &mut transposed,
+      width: self.height,
+      height: self.width,
+      stride: self.height * 4,
+    };
+    columns.blur_rows(radius);
+    transpose(&transposed, self.height * 4, self.data, self.stride, self.height, self.width);
+  }
+
+  fn blur_rows(&mut self, radius: usize) {
+    let (width, stride) = (self.width, self.stride);
+    self.for_each_chunk(self.rows_per_chunk(), |_, chunk| {
+      let mut line = Vec::with_capacity(width);
+      let mut sums = Vec::with_capacity(width + 1);
+      for row in chunk.chunks_mut(stride) {
+        let row = &mut row[..width * 4];
+        line.clear();
+        line.extend(row.chunks_exact(4).map(|p| [p[0], p[1], p[2], p[3]]));
+        blur_line(&mut line, radius, &mut sums);
+        for (dst, src) in row.chunks_exact_mut(4).zip(line.iter()) {
+          dst.copy_from_slice(src);
+        }
+      }
+    });
+  }
+
+  pub fn pixelate(&mut self, size: usize) {
+    if size <= 1 {
+      return;
+    }
+    let (width, stride) = (self.width, self.stride);
+    // Chunks hold whole rows of blocks, as many as it takes to give every core one chunk
+    let rows = self.height.div_ceil(size).div_ceil(threads()) * size;
+    self.for_each_chunk(rows, |_, chunk| {
+      let rows = chunk.len().div_ceil(stride);
+      for block_y in (0..rows).step_by(size) {
+        let y_end = (block_y + size).min(rows);
+        for block_x in (0..width).step_by(size) {
+          let x_end = (block_x + size).min(width);
+          let mut sum = [0u64; 4];
+          for y in block_y..y_end {
+            for x in block_x..x_end {
+              let i = y * stride + x * 4;
+              for c in 0..4 {
+                sum[c] += chunk[i + c] as u64;
+              }
+            }
+          }
+          let count = ((x_end - block_x) * (y_end - block_y)) as u64;
+          let average = sum.map(|v| (v / count) as u8);
+          for y in block_y..y_end {
+            for x in block_x..x_end {
+              let i = y * stride + x * 4;
+              chunk[i..i + 4].copy_from_slice(&average);
+            }
+          }
+        }
+      }
+    });
+  }
+
+  pub fn greyscale(&mut self) {
+    self.for_each_pixel(|pixel| {
+      // Channels are stored as B, G, R, A in memory
+      let luma = 0.0722 * pixel[0] as f64 + 0.7152 * pixel[1] as f64 + 0.2126 * pixel[2] as f64;
+      let luma = luma.round() as u8;
+      pixel[..3].fill(luma);
+    });
+  }
+
+  /// Shift every channel by `amount` between -1 and 1
+  pub fn brightness(&mut self, amount: f64) {
+    let offset = amount * 255.0;
+    self.for_each_pixel(|pixel| {
+      for c in &mut pixel[..3] {
+        *c = (*c as f64 + offset).round().clamp(0.0, 255.0) as u8;
+      }
+    });
+  }
+
+  /// Scale the distance of every channel from mid grey by `factor`
+  pub fn contrast(&mut self, factor: f64) {
+    self.for_each_pixel(|pixel| {
+      for c in &mut pixel[..3] {
+        *c = ((*c as f64 - 127.5) * factor + 127.5).round().clamp(0.0, 255.0) as u8;
+      }
+    });
+  }
+
+  /// Blend every pixel towards `color` by `amount` between 0 and 1
+  pub fn overlay(&mut self, color: Color, amount: f64) {
+    let amount = amount.clamp(0.0, 1.0);
+    let target = [color.b, color.g, color.r].map(|v| v * 255.0);
+    self.for_each_pixel(|pixel| {
+      for (c, target) in pixel[..3].iter_mut().zip(target) {
+        *c = (*c as f64 * (1.0 - amount) + target * amount).round() as u8;
+      }
+    });
+  }
+
+  /// Add grain of up to `amount` between 0 and 1 to every pixel, the same for every run
+  pub fn noise(&mut self, amount: f64) {
+    if amount <= 0.0 {
+      return;
+    }
+    let scale = amount.min(1.0) * 255.0;
+    self.for_each_row(|y, row| {
+      // Seed per row so the result does not depend on how rows are split across threads
+      let mut state = (y as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+      for pixel in row.chunks_exact_mut(4) {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let offset = ((state >> 40) as f64 / (1u64 << 24) as f64 - 0.5) * scale;
+        for c in &mut pixel[..3] {
+          *c = (*c as f64 + offset).round().clamp(0.0, 255.0) as u8;
+        }
+      }
+    });
+  }
+
+  /// Darken towards the corners, `strength` of 1 makes the corners black
+  pub fn vignette(&mut self, strength: f64) {
+    if strength <= 0.0 {
+      return;
+    }
+    let (cx, cy) = (self.width as f64 / 2.0, self.height as f64 / 2.0);
+    let max_distance = cx * cx + cy * cy;
+    self.for_each_row(|y, row| {
+      let dy = y as f64 + 0.5 - cy;
+      for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+        let dx = x as f64 + 0.5 - cx;
+        let factor = (1.0 - strength * (dx * dx + dy * dy) / max_distance).max(0.0);
+        for c in &mut pixel[..3] {
+          *c = (*c as f64 * factor) as u8;
+        }
+      }
+    });
+  }
+}
+
+/// Number of threads effects are split across
+fn threads() -> usize {
+  std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Copy `rows` rows of `columns` pixels from `src` into `dst` with rows and columns swapped
+fn transpose(src: &[u8], src_stride: usize, dst: &mut [u8], dst_stride: usize, columns: usize, rows: usize) {
+  for y in 0..rows {
+    for x in 0..columns {
+      let from = y * src_stride + x * 4;
+      let to = x * dst_stride + y * 4;
+      dst[to..to + 4].copy_from_slice(&src[from..from + 4]);
+    }
+  }
+}
+
+/// Replace every pixel with the average of the pixels within `radius`, clamped to the line
+fn blur_line(line: &mut [[u8; 4]], radius: usize, sums: &mut Vec<[u32; 4]>) {
+  sums.clear();
+  sums.push([0; 4]);
+  for pixel in line.iter() {
+    let last = *sums.last().unwrap();
+    sums.push(std::array::from_fn(|c| last[c] + pixel[c] as u32));
+  }
+  let len = line.len();
+  for (i, pixel) in line.iter_mut().enumerate() {
+    let low = i.saturating_sub(radius);
+    let high = (i + radius + 1).min(len);
+    let count = (high - low) as u32;
+    *pixel = std::array::from_fn(|c| ((sums[high][c] - sums[low][c]) / count) as u8);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Image of `width` by `height` pixels with `padding` unused bytes after every row
+  fn image(data: &mut Vec<u8>, width: usize, height: usize, padding: usize) -> ImageData<'_> {
+    let stride = width * 4 + padding;
+    data.resize(stride * height, 0);
+    ImageData {
+      data,
+      width,
+      height,
+      stride,
+    }
+  }
+
+  #[test]
+  fn blur_line_averages_within_radius() {
+    let mut line = vec![[0, 0, 0, 255], [30, 60, 90, 255], [0, 0, 0, 255], [90, 30, 60, 255]];
+    blur_line(&mut line, 1, &mut Vec::new());
+    assert_eq!(
+      line,
+      vec![
+        [15, 30, 45, 255],
+        [10, 20, 30, 255],
+        [40, 30, 50, 255],
+        [45, 15, 30, 255]
+      ]
+    );
+  }
+
+  #[test]
+  fn blur_line_radius_covers_line() {
+    let mut line = vec![[10, 0, 0, 0], [20, 0, 0, 0], [30, 0, 0, 0]];
+    blur_line(&mut line, 5, &mut Vec::new());
+    assert_eq!(line, vec![[20, 0, 0, 0]; 3]);
+  }
+
+  #[test]
+  fn pixelate_averages_blocks() {
+    let mut data = Vec::new();
+    let mut image = image(&mut data, 3, 3, 4);
+    let stride = image.stride;
+    for y in 0..3 {
+      for x in 0..3 {
+        let i = y * stride + x * 4;
+        image.data[i..i + 4].copy_from_slice(&[(y * 3 + x) as u8 * 10, 0, 0, 255]);
+      }
+    }
+    image.pixelate(2);
+    let pixel = |x: usize, y: usize| data[y * stride + x * 4];
+    // Full block, partial blocks on the right and bottom edges, and the lone corner
+    assert_eq!([pixel(0, 0), pixel(1, 0), pixel(0, 1), pixel(1, 1)], [20; 4]);
+    assert_eq!([pixel(2, 0), pixel(2, 1)], [35; 2]);
+    assert_eq!([pixel(0, 2), pixel(1, 2)], [65; 2]);
+    assert_eq!(pixel(2, 2), 80);
+    // Padding is left alone
+    assert!(data.chunks(stride).all(|row| row[12..] == [0; 4]));
+  }
+
+  #[test]
+  fn pixelate_many_rows_of_blocks() {
+    let mut data = Vec::new();
+    let mut image = image(&mut data, 2, 64, 0);
+    for (y, row) in image.data.chunks_mut(8).enumerate() {
+      row.fill(y as u8);
+    }
+    image.pixelate(2);
+    for (y, row) in data.chunks(8).enumerate() {
+      // Rows 2k and 2k + 1 average to 2k
+      let expected = (y / 2 * 2) as u8;
+      assert!(row.iter().all(|&c| c == expected), "row {}", y);
+    }
+  }
+
+  #[test]
+  fn greyscale_uses_luma() {
+    let mut data = vec![255, 0, 0, 200, 0, 255, 0, 200, 0, 0, 255, 200];
+    image(&mut data, 3, 1, 0).greyscale();
+    assert_eq!(data, vec![18, 18, 18, 200, 182, 182, 182, 200, 54, 54, 54, 200]);
+  }
+
+  #[test]
+  fn brightness_clamps() {
+    let mut data = vec![10, 128, 250, 100];
+    image(&mut data, 1, 1, 0).brightness(0.1);
+    assert_eq!(data, vec![36, 154, 255, 100]);
+    image(&mut data, 1, 1, 0).brightness(-1.0);
+    assert_eq!(data, vec![0, 0, 0, 100]);
+  }
+
+  #[test]
+  fn contrast_clamps() {
+    let mut data = vec![0, 100, 200, 255];
+    image(&mut data, 1, 1, 0).contrast(2.0);
+    assert_eq!(data, vec![0, 73, 255, 255]);
+    let mut data = vec![0, 100, 255, 255];
+    image(&mut data, 1, 1, 0).contrast(0.0);
+    assert_eq!(data, vec![128, 128, 128, 255]);
+  }
+
+  #[test]
+  fn transpose_non_square() {
+    // Three columns by two rows with four bytes of padding per row
+    let src_stride = 3 * 4 + 4;
+    let mut src = vec![0xff; src_stride * 2];
+    for y in 0..2 {
+      for x in 0..3 {
+        let i = y * src_stride + x * 4;
+        src[i..i + 4].copy_from_slice(&[x as u8, y as u8, 0, 0]);
+      }
+    }
+    let mut dst = vec![0; 2 * 4 * 3];
+    transpose(&src, src_stride, &mut dst, 2 * 4, 3, 2);
+    for (x, row) in dst.chunks(8).enumerate() {
+      assert_eq!(row, [x as u8, 0, 0, 0, x as u8, 1, 0, 0]);
+    }
+    let mut back = vec![0xff; src_stride * 2];
+    transpose(&dst, 2 * 4, &mut back, src_stride, 2, 3);
+    assert_eq!(back, src);
+  }
+}
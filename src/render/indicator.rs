@@ -1,39 +1,148 @@
-use crate::{
-  args::Color,
-  shm::slot::{BufferSlot, BufferSlotPool},
-};
-
-pub const INDICATOR_BLOCK_COUNT: usize = 4;
-
-pub fn draw_indicator(
-  pool: &mut BufferSlotPool,
-  block_colors: [Color; INDICATOR_BLOCK_COUNT],
-  bg_color: Color,
-) -> &mut BufferSlot {
-  let block_size = 10;
-  let block_spacing = 30;
-  let indicator_width = (INDICATOR_BLOCK_COUNT * block_size + (INDICATOR_BLOCK_COUNT - 1) * block_spacing) as u32;
-  let indicator_height = block_size as u32;
-  let (buffer, data) = pool.get_next_buffer(indicator_width, indicator_height);
-  let surface = unsafe {
-    cairo::ImageSurface::create_for_data_unsafe(
-      data.first_mut().unwrap(),
-      cairo::Format::ARgb32,
-      buffer.width().try_into().unwrap(),
-      buffer.height().try_into().unwrap(),
-      buffer.stride().try_into().unwrap(),
-    )
-    .unwrap()
-  };
-  let context = cairo::Context::new(&surface).unwrap();
-  context.set_source_rgb(bg_color.r, bg_color.g, bg_color.b);
-  context.paint().unwrap();
-  for i in 0..INDICATOR_BLOCK_COUNT {
-    let x = i * (block_size + block_spacing);
-    context.rectangle(x as f64, 0.0, block_size as f64, block_size as f64);
-    let color = block_colors[i];
-    context.set_source_rgb(color.r, color.g, color.b);
-    context.fill().unwrap();
-  }
-  buffer
+use std::f64::consts::PI;
+
+use clap::ValueEnum;
+
+use crate::args::Color;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum IndicatorStyle {
+  /// Row of fixed squares
+  Blocks,
+  /// Circular ring split into arc segments
+  Ring,
+  /// One dot per typed character
+  Dots,
+}
+
+#[derive(Clone, Copy)]
+pub enum IndicatorState {
+  Full(Color),
+  Input(usize),
+}
+
+#[derive(Clone, Copy)]
+pub struct InputColors {
+  pub cursor: Color,
+  pub cursor_increment: Color,
+  pub trail: Color,
+  pub trail_increment: Color,
+}
+
+#[derive(Clone)]
+pub struct Indicator {
+  pub style: IndicatorStyle,
+  pub count: usize,
+  pub size: u32,
+  pub spacing: u32,
+  pub ring_radius: u32,
+  pub input_colors: InputColors,
+}
+
+impl Indicator {
+  pub fn measure(&self, state: IndicatorState) -> (u32, u32) {
+    self.dimensions(self.colors(state).len())
+  }
+
+  pub fn draw(&self, context: &cairo::Context, state: IndicatorState) {
+    let colors = self.colors(state);
+    match self.style {
+      IndicatorStyle::Blocks => self.draw_blocks(context, &colors),
+      IndicatorStyle::Ring => self.draw_ring(context, &colors),
+      IndicatorStyle::Dots => self.draw_dots(context, &colors),
+    }
+  }
+
+  fn colors(&self, state: IndicatorState) -> Vec<Color> {
+    match (self.style, state) {
+      (IndicatorStyle::Dots, IndicatorState::Input(len)) => self.segment_colors(len, state),
+      _ => self.segment_colors(self.count, state),
+    }
+  }
+
+  /// Map an indicator state onto the colours of `count` segments
+  fn segment_colors(&self, count: usize, state: IndicatorState) -> Vec<Color> {
+    match state {
+      IndicatorState::Full(color) => vec![color; count],
+      IndicatorState::Input(0) => vec![self.input_colors.trail; count],
+      IndicatorState::Input(len) => {
+        let colors = self.input_colors;
+        let strength = (len - 1) / count;
+        let pos = (len - 1) % count;
+        (0..count)
+          .map(|i| {
+            if i < pos {
+              add_color(colors.trail, colors.trail_increment, strength + 1)
+            } else if i == pos {
+              add_color(colors.cursor, colors.cursor_increment, strength)
+            } else {
+              add_color(colors.trail, colors.trail_increment, strength)
+            }
+          })
+          .collect()
+      }
+    }
+  }
+
+  fn dimensions(&self, segments: usize) -> (u32, u32) {
+    match self.style {
+      IndicatorStyle::Blocks | IndicatorStyle::Dots => {
+        let segments = std::cmp::max(segments as u32, 1);
+        (segments * self.size + (segments - 1) * self.spacing, self.size)
+      }
+      IndicatorStyle::Ring => {
+        let diameter = (self.ring_radius + self.size) * 2;
+        (diameter, diameter)
+      }
+    }
+  }
+
+  fn draw_blocks(&self, context: &cairo::Context, colors: &[Color]) {
+    for (i, color) in colors.iter().enumerate() {
+      let x = i as u32 * (self.size + self.spacing);
+      context.rectangle(x as f64, 0.0, self.size as f64, self.size as f64);
+      context.set_source_rgba(color.r, color.g, color.b, color.a);
+      context.fill().unwrap();
+    }
+  }
+
+  fn draw_ring(&self, context: &cairo::Context, colors: &[Color]) {
+    let center = (self.ring_radius + self.size) as f64;
+    let radius = self.ring_radius as f64 + self.size as f64 / 2.0;
+    let segment = 2.0 * PI / colors.len() as f64;
+    // Leave a gap the width of the spacing between segments
+    let gap = if colors.len() > 1 {
+      (self.spacing as f64 / radius).min(segment / 2.0)
+    } else {
+      0.0
+    };
+    context.set_line_width(self.size as f64);
+    for (i, color) in colors.iter().enumerate() {
+      // Start from the top of the ring and go clockwise
+      let start = i as f64 * segment - PI / 2.0 + gap / 2.0;
+      context.new_sub_path();
+      context.arc(center, center, radius, start, start + segment - gap);
+      context.set_source_rgba(color.r, color.g, color.b, color.a);
+      context.stroke().unwrap();
+    }
+  }
+
+  fn draw_dots(&self, context: &cairo::Context, colors: &[Color]) {
+    let radius = self.size as f64 / 2.0;
+    for (i, color) in colors.iter().enumerate() {
+      let x = i as u32 * (self.size + self.spacing);
+      context.new_sub_path();
+      context.arc(x as f64 + radius, radius, radius, 0.0, 2.0 * PI);
+      context.set_source_rgba(color.r, color.g, color.b, color.a);
+      context.fill().unwrap();
+    }
+  }
+}
+
+fn add_color(color: Color, inc_color: Color, strength: usize) -> Color {
+  Color {
+    r: color.r + inc_color.r * strength as f64,
+    g: color.g + inc_color.g * strength as f64,
+    b: color.b + inc_color.b * strength as f64,
+    a: color.a,
+  }
 }
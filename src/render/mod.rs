@@ -1,3 +1,29 @@
 pub mod background;
-pub mod clock;
+pub mod effects;
 pub mod indicator;
+pub mod text;
+
+use crate::shm::format::cairo_format;
+use crate::shm::slot::BufferSlot;
+
+/// Cairo context drawing directly into the mapped memory of a buffer slot.
+/// The context must not outlive the slot data it was created from.
+pub fn buffer_context(buffer: &BufferSlot, data: &mut [u8]) -> cairo::Context {
+  let surface = unsafe {
+    cairo::ImageSurface::create_for_data_unsafe(
+      data.first_mut().unwrap(),
+      cairo_format(buffer.format()),
+      buffer.width().try_into().unwrap(),
+      buffer.height().try_into().unwrap(),
+      buffer.stride().try_into().unwrap(),
+    )
+    .unwrap()
+  };
+  cairo::Context::new(&surface).unwrap()
+}
+
+/// Throwaway cairo context for measuring content before a buffer is requested
+pub fn measure_context() -> cairo::Context {
+  let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, 1, 1).unwrap();
+  cairo::Context::new(&surface).unwrap()
+}
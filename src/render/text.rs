@@ -0,0 +1,113 @@
+use cairo::{Antialias, FontOptions, HintStyle};
+use clap::ValueEnum;
+use pango::FontDescription;
+
+use crate::{args::Color, render::measure_context};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum TextAlign {
+  Left,
+  Center,
+  Right,
+}
+
+#[derive(Clone)]
+pub struct TextStyle {
+  pub color: Color,
+  pub font: FontDescription,
+}
+
+impl TextStyle {
+  /// Style from a Pango font description such as "Inter Bold 48". The size in points is only
+  /// used when the description does not specify one, so both are read the same way.
+  pub fn new(color: Color, font: &str, size: f64) -> Self {
+    let mut font = FontDescription::from_string(font);
+    if font.size() == 0 {
+      font.set_size(pango_size(size));
+    }
+    Self { color, font }
+  }
+
+  /// The same style at `size` points
+  pub fn with_size(&self, size: f64) -> Self {
+    let mut style = self.clone();
+    style.font.set_size(pango_size(size));
+    style
+  }
+}
+
+fn pango_size(points: f64) -> i32 {
+  (points * pango::SCALE as f64).round() as i32
+}
+
+#[derive(Clone, Copy)]
+pub struct TextMetrics {
+  pub width: u32,
+  pub height: u32,
+}
+
+fn create_layout(context: &cairo::Context, style: &TextStyle, text: &str) -> pango::Layout {
+  let mut font_options = FontOptions::new().unwrap();
+  font_options.set_hint_style(HintStyle::Full);
+  font_options.set_antialias(Antialias::Subpixel);
+  context.set_font_options(&font_options);
+  let layout = pangocairo::create_layout(context).unwrap();
+  layout.set_font_description(Some(&style.font));
+  layout.set_text(text);
+  layout
+}
+
+fn wrap_layout(layout: &pango::Layout, width: u32, align: TextAlign) {
+  layout.set_width(width as i32 * pango::SCALE);
+  layout.set_wrap(pango::WrapMode::WordChar);
+  layout.set_alignment(match align {
+    TextAlign::Left => pango::Alignment::Left,
+    TextAlign::Center => pango::Alignment::Center,
+    TextAlign::Right => pango::Alignment::Right,
+  });
+}
+
+fn layout_metrics(layout: &pango::Layout) -> TextMetrics {
+  // Logical extents keep the height constant for a line while the text changes
+  let (_, logical) = layout.pixel_extents();
+  TextMetrics {
+    width: logical.width().max(0) as u32,
+    height: logical.height().max(0) as u32,
+  }
+}
+
+pub fn measure_text(style: &TextStyle, text: &str) -> TextMetrics {
+  layout_metrics(&create_layout(&measure_context(), style, text))
+}
+
+/// Measure text wrapped at word boundaries to at most `max_width`
+pub fn measure_paragraph(style: &TextStyle, text: &str, max_width: u32, align: TextAlign) -> TextMetrics {
+  let layout = create_layout(&measure_context(), style, text);
+  wrap_layout(&layout, max_width, align);
+  layout_metrics(&layout)
+}
+
+/// Draw a single line of text with its top left corner at `x`, `y`
+pub fn draw_text(context: &cairo::Context, style: &TextStyle, text: &str, x: f64, y: f64) {
+  let layout = create_layout(context, style, text);
+  context.set_source_rgba(style.color.r, style.color.g, style.color.b, style.color.a);
+  context.move_to(x, y);
+  pangocairo::show_layout(context, &layout);
+}
+
+/// Draw wrapped text aligned within a block of `width`
+pub fn draw_paragraph(
+  context: &cairo::Context,
+  style: &TextStyle,
+  text: &str,
+  width: u32,
+  align: TextAlign,
+  x: f64,
+  y: f64,
+) {
+  let layout = create_layout(context, style, text);
+  wrap_layout(&layout, width, align);
+  context.set_source_rgba(style.color.r, style.color.g, style.color.b, style.color.a);
+  context.move_to(x, y);
+  pangocairo::show_layout(context, &layout);
+}
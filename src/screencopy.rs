@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use wayland_client::globals::GlobalList;
+use wayland_client::protocol::{wl_output, wl_shm};
+use wayland_client::{delegate_noop, Connection, Dispatch, Proxy, QueueHandle, WEnum};
+use wayland_protocols_wlr::screencopy::v1::client::{zwlr_screencopy_frame_v1, zwlr_screencopy_manager_v1};
+
+use crate::shm::raw::{DummyObjectData, RawPool};
+
+/// Copy of an output's content in ARGB32 layout
+pub struct Screenshot {
+  width: u32,
+  height: u32,
+  stride: u32,
+  data: Vec<u8>,
+}
+
+impl Screenshot {
+  pub fn into_image(self) -> cairo::ImageSurface {
+    cairo::ImageSurface::create_for_data(
+      self.data,
+      cairo::Format::Rgb24,
+      self.width as i32,
+      self.height as i32,
+      self.stride as i32,
+    )
+    .unwrap()
+  }
+}
+
+#[derive(Default)]
+struct Frame {
+  buffer: Option<(wl_shm::Format, u32, u32, u32)>,
+  y_invert: bool,
+  copied: bool,
+  ready: bool,
+  failed: bool,
+}
+
+struct CaptureState {
+  frames: Vec<Frame>,
+}
+
+/// Capture every output through wlr-screencopy. Must run before the session is locked,
+/// otherwise the lock surfaces themselves would be captured.
+pub fn capture_outputs(
+  connection: &Connection,
+  globals: &GlobalList,
+  wl_shm: &wl_shm::WlShm,
+  outputs: &[wl_output::WlOutput],
+) -> Vec<Option<Screenshot>> {
+  let mut queue = connection.new_event_queue::<CaptureState>();
+  let qh = queue.handle();
+  let manager: zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1 = match globals.bind(&qh, 1..=3, ()) {
+    Ok(manager) => manager,
+    Err(_) => {
+      eprintln!("Compositor does not support wlr-screencopy, skipping screenshot");
+      return outputs.iter().map(|_| None).collect();
+    }
+  };
+  let mut state = CaptureState {
+    frames: outputs.iter().map(|_| Frame::default()).collect(),
+  };
+  let frames: Vec<_> = outputs
+    .iter()
+    .enumerate()
+    .map(|(i, output)| manager.capture_output(0, output, &qh, i))
+    .collect();
+  // Buffer parameters are sent right after the capture request
+  if let Err(err) = queue.roundtrip(&mut state) {
+    eprintln!("Failed to request screenshots: {}", err);
+    for frame in frames {
+      frame.destroy();
+    }
+    manager.destroy();
+    return outputs.iter().map(|_| None).collect();
+  }
+
+  let mut copies = Vec::new();
+  for (i, frame) in frames.iter().enumerate() {
+    if let Some((format, width, height, stride)) = state.frames[i].buffer.filter(|_| !state.frames[i].failed) {
+      let mut pool = match RawPool::create((stride * height) as usize, wl_shm) {
+        Ok(pool) => pool,
+        Err(err) => {
+          eprintln!("Failed to allocate screenshot buffer: {}", err);
+          continue;
+        }
+      };
+      let buffer = pool.create_buffer(
+        0,
+        width as i32,
+        height as i32,
+        stride as i32,
+        format,
+        Arc::new(DummyObjectData),
+      );
+      frame.copy(&buffer);
+      state.frames[i].copied = true;
+      copies.push((i, pool, buffer));
+    }
+  }
+  while state.frames.iter().any(|f| f.copied && !f.ready && !f.failed) {
+    // Outputs whose copy did not finish fall back to the background
+    if let Err(err) = queue.blocking_dispatch(&mut state) {
+      eprintln!("Failed to take screenshots: {}", err);
+      break;
+    }
+  }
+
+  let mut screenshots: Vec<Option<Screenshot>> = outputs.iter().map(|_| None).collect();
+  for (i, mut pool, buffer) in copies {
+    let frame = &state.frames[i];
+    if let Some((_, width, height, stride)) = frame.buffer.filter(|_| frame.ready) {
+      let mmap = pool.mmap();
+      let mut data = Vec::with_capacity((stride * height) as usize);
+      for y in 0..height {
+        let y = if frame.y_invert { height - 1 - y } else { y };
+        data.extend_from_slice(&mmap[(y * stride) as usize..][..stride as usize]);
+      }
+      screenshots[i] = Some(Screenshot {
+        width,
+        height,
+        stride,
+        data,
+      });
+    }
+    buffer.destroy();
+  }
+  for frame in frames {
+    frame.destroy();
+  }
+  manager.destroy();
+  connection.flush().unwrap();
+  screenshots
+}
+
+delegate_noop!(CaptureState: zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1);
+
+impl Dispatch<zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1, usize> for CaptureState {
+  fn event(
+    state: &mut Self,
+    _proxy: &zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
+    event: <zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1 as Proxy>::Event,
+    data: &usize,
+    _conn: &Connection,
+    _qhandle: &QueueHandle<Self>,
+  ) {
+    let frame = &mut state.frames[*data];
+    match event {
+      zwlr_screencopy_frame_v1::Event::Buffer {
+        format: WEnum::Value(format),
+        width,
+        height,
+        stride,
+      } => {
+        // Only formats sharing cairo's ARGB32 memory layout are handled
+        if frame.buffer.is_none() && matches!(format, wl_shm::Format::Xrgb8888 | wl_shm::Format::Argb8888) {
+          frame.buffer = Some((format, width, height, stride));
+        }
+      }
+      zwlr_screencopy_frame_v1::Event::Flags {
+        flags: WEnum::Value(flags),
+      } => {
+        frame.y_invert = flags.contains(zwlr_screencopy_frame_v1::Flags::YInvert);
+      }
+      zwlr_screencopy_frame_v1::Event::Ready { .. } => frame.ready = true,
+      zwlr_screencopy_frame_v1::Event::Failed => frame.failed = true,
+      _ => {}
+    }
+  }
+}
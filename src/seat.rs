@@ -3,6 +3,7 @@ use wayland_client::protocol::{wl_keyboard, wl_pointer, wl_seat};
 use wayland_client::{Dispatch, QueueHandle, WEnum};
 use xkbcommon::xkb::{
   ffi::XKB_CONTEXT_NO_FLAGS, Context, Keymap, Keysym, KEYMAP_COMPILE_NO_FLAGS, KEYMAP_FORMAT_TEXT_V1,
+  STATE_MODS_EFFECTIVE,
 };
 
 pub struct AppSeat {
@@ -19,10 +20,25 @@ impl AppSeat {
       wl_pointer: None,
     }
   }
+
+  /// Whether all of the given xkb modifier names are currently active
+  pub fn modifiers_active(&self, modifiers: &[&str]) -> bool {
+    match self.xkb_state.as_ref() {
+      Some(xkb_state) => modifiers
+        .iter()
+        .all(|modifier| xkb_state.mod_name_is_active(modifier, STATE_MODS_EFFECTIVE)),
+      None => false,
+    }
+  }
 }
 
 pub trait DispatchKeyEvents {
   fn event(state: &mut Self, keysym: Keysym, codepoint: u32);
+  fn release(state: &mut Self, keysym: Keysym);
+  fn leave(state: &mut Self);
+  /// Pointer motion, button or scroll, only interesting as a sign of activity.
+  /// `motion` is false for buttons and scrolling
+  fn pointer(state: &mut Self, motion: bool);
 }
 
 impl<State> Dispatch<wl_seat::WlSeat, (), State> for AppSeat
@@ -105,8 +121,15 @@ where
             let codepoint = xkb_state.key_get_utf32(key + 8);
             DispatchKeyEvents::event(state, keysym, codepoint);
           }
+        } else if let wl_keyboard::KeyState::Released = key_state {
+          if let Some(xkb_state) = state.as_mut().xkb_state.as_ref() {
+            let keysym = xkb_state.key_get_one_sym(key + 8);
+            DispatchKeyEvents::release(state, keysym);
+          }
         }
       }
+    } else if let wl_keyboard::Event::Leave { .. } = event {
+      DispatchKeyEvents::leave(state);
     } else if let wl_keyboard::Event::Modifiers {
       mods_depressed,
       mods_latched,
@@ -125,18 +148,22 @@ where
 impl<State> Dispatch<wl_pointer::WlPointer, (), State> for AppSeat
 where
   State: Dispatch<wl_pointer::WlPointer, ()>,
+  State: DispatchKeyEvents,
   State: AsMut<Self>,
 {
   fn event(
-    _state: &mut State,
+    state: &mut State,
     proxy: &wl_pointer::WlPointer,
     event: <wl_pointer::WlPointer as wayland_client::Proxy>::Event,
     _data: &(),
     _conn: &wayland_client::Connection,
     _qhandle: &QueueHandle<State>,
   ) {
-    if let wl_pointer::Event::Enter { serial, .. } = event {
-      proxy.set_cursor(serial, None, 0, 0);
+    match event {
+      wl_pointer::Event::Enter { serial, .. } => proxy.set_cursor(serial, None, 0, 0),
+      wl_pointer::Event::Motion { .. } => DispatchKeyEvents::pointer(state, true),
+      wl_pointer::Event::Button { .. } | wl_pointer::Event::Axis { .. } => DispatchKeyEvents::pointer(state, false),
+      _ => {}
     }
   }
 }
@@ -0,0 +1,47 @@
+use wayland_client::protocol::wl_shm;
+
+/// Pixel formats advertised by the compositor through `wl_shm.format`
+#[derive(Default)]
+pub struct ShmFormats {
+  formats: Vec<wl_shm::Format>,
+}
+
+impl ShmFormats {
+  /// Translucent format for the widget subsurfaces, cairo only draws alpha with 8 bit channels
+  pub const OVERLAY: wl_shm::Format = wl_shm::Format::Argb8888;
+
+  pub fn push(&mut self, format: wl_shm::Format) {
+    if !self.formats.contains(&format) {
+      self.formats.push(format);
+    }
+  }
+
+  fn supports(&self, format: wl_shm::Format) -> bool {
+    // Both 8 bit formats are required by the protocol even if not advertised
+    matches!(format, wl_shm::Format::Argb8888 | wl_shm::Format::Xrgb8888) || self.formats.contains(&format)
+  }
+
+  /// Opaque format for the base surface, `deep` asks for 10 bit channels when available
+  pub fn background(&self, deep: bool) -> wl_shm::Format {
+    if deep && self.supports(wl_shm::Format::Xrgb2101010) {
+      wl_shm::Format::Xrgb2101010
+    } else {
+      wl_shm::Format::Xrgb8888
+    }
+  }
+}
+
+/// Cairo format with the same memory layout as a `wl_shm` format
+pub fn cairo_format(format: wl_shm::Format) -> cairo::Format {
+  match format {
+    wl_shm::Format::Argb8888 => cairo::Format::ARgb32,
+    wl_shm::Format::Xrgb8888 => cairo::Format::Rgb24,
+    wl_shm::Format::Xrgb2101010 => cairo::Format::Rgb30,
+    _ => panic!("unsupported shm format {:?}", format),
+  }
+}
+
+/// Row length in bytes of a buffer, as cairo expects it for the format
+pub fn stride(format: wl_shm::Format, width: u32) -> u32 {
+  cairo_format(format).stride_for_width(width).unwrap() as u32
+}
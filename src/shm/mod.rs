@@ -1,2 +1,3 @@
-mod raw;
+pub mod format;
+pub mod raw;
 pub mod slot;
@@ -1,42 +1,33 @@
-use memfd::MemfdOptions;
+use memfd::{FileSeal, MemfdOptions};
 use memmap::{MmapMut, MmapOptions};
+use std::io;
 use std::os::fd::AsFd;
 use std::{fs::File, sync::Arc};
 use wayland_client::protocol::{wl_buffer, wl_shm, wl_shm_pool};
 use wayland_client::{backend::ObjectData, Proxy, WEnum};
 
 pub struct RawPool {
-  len: usize,
-  mem_file: File,
-  mmap: MmapMut,
+  memory: SealedMemory,
   pool: wl_shm_pool::WlShmPool,
 }
 
 impl RawPool {
-  pub fn create(len: usize, wl_shm: &wl_shm::WlShm) -> Self {
-    let mem_file = MemfdOptions::default().create("minlock_buffer").unwrap().into_file();
-    mem_file.set_len(len as u64).unwrap();
+  pub fn create(len: usize, wl_shm: &wl_shm::WlShm) -> io::Result<Self> {
+    let memory = SealedMemory::create(len)?;
     let request = wl_shm::Request::CreatePool {
-      fd: mem_file.as_fd(),
+      fd: memory.file.as_fd(),
       size: len as i32,
     };
-    let wl_shm_pool = wl_shm.send_constructor(request, Arc::new(DummyObjectData)).unwrap();
-    let mmap = unsafe { MmapOptions::new().map_mut(&mem_file).unwrap() };
-    Self {
-      len,
-      mem_file,
-      mmap,
-      pool: wl_shm_pool,
-    }
+    let pool = wl_shm.send_constructor(request, Arc::new(DummyObjectData)).unwrap();
+    Ok(Self { memory, pool })
   }
 
-  pub fn resize(&mut self, size: usize) {
-    if size > self.len {
-      self.len = size;
-      self.mem_file.set_len(size as u64).unwrap();
-      self.mmap = unsafe { MmapOptions::new().map_mut(&self.mem_file).unwrap() };
+  /// Grow the pool to `size` bytes, the protocol and the seal only allow growing so smaller sizes are ignored
+  pub fn resize(&mut self, size: usize) -> io::Result<()> {
+    if self.memory.grow(size)? {
       self.pool.resize(size as i32);
     }
+    Ok(())
   }
 
   pub fn create_buffer(
@@ -59,20 +50,81 @@ impl RawPool {
   }
 
   pub fn mmap(&mut self) -> &mut MmapMut {
-    &mut self.mmap
+    &mut self.memory.mmap
   }
   pub fn len(&self) -> usize {
-    self.len
+    self.memory.len
+  }
+}
+
+/// Memfd shared with the compositor, mapped in full
+struct SealedMemory {
+  len: usize,
+  file: File,
+  mmap: MmapMut,
+}
+
+impl SealedMemory {
+  fn create(len: usize) -> io::Result<Self> {
+    let memfd = MemfdOptions::default()
+      .allow_sealing(true)
+      .create("minlock_buffer")
+      .map_err(os_error)?;
+    memfd.as_file().set_len(len as u64)?;
+    // Neither the compositor nor anyone else holding the fd may shrink the file under a mapping,
+    // touching pages past the end would raise SIGBUS. Growing stays possible for resize
+    memfd.add_seal(FileSeal::SealShrink).map_err(os_error)?;
+    memfd.add_seal(FileSeal::SealSeal).map_err(os_error)?;
+    let file = memfd.into_file();
+    let mmap = map(&file, len)?;
+    Ok(Self { len, file, mmap })
+  }
+
+  /// Grow to `size` bytes and remap, returns false and keeps the current size if it is not larger.
+  /// The previous mapping stays in place when growing fails
+  fn grow(&mut self, size: usize) -> io::Result<bool> {
+    if size <= self.len {
+      return Ok(false);
+    }
+    self.file.set_len(size as u64)?;
+    self.mmap = map(&self.file, size)?;
+    self.len = size;
+    Ok(true)
+  }
+}
+
+/// The OS error behind a memfd error
+fn os_error(err: memfd::Error) -> io::Error {
+  match err {
+    memfd::Error::Create(err) | memfd::Error::AddSeals(err) | memfd::Error::GetSeals(err) => err,
   }
 }
 
+/// Map exactly `len` bytes after checking the file really is that long.
+///
+/// Accessing a shared mapping past the end of its file raises SIGBUS instead of returning an error, so the
+/// length is only trusted together with the seals: `F_SEAL_SHRINK` keeps the compositor or anyone else holding
+/// the fd from truncating the file under the mapping, and `F_SEAL_SEAL` keeps that seal from being lifted.
+/// The file is only ever grown before it is mapped again, so a file shorter than `len` means the memory was not
+/// set up as expected and is reported rather than mapped.
+fn map(file: &File, len: usize) -> io::Result<MmapMut> {
+  let file_len = file.metadata()?.len();
+  if file_len < len as u64 {
+    return Err(io::Error::new(
+      io::ErrorKind::UnexpectedEof,
+      format!("shared memory file is {} bytes, expected at least {}", file_len, len),
+    ));
+  }
+  unsafe { MmapOptions::new().len(len).map_mut(file) }
+}
+
 impl Drop for RawPool {
   fn drop(&mut self) {
     self.pool.destroy();
   }
 }
 
-struct DummyObjectData;
+pub struct DummyObjectData;
 impl ObjectData for DummyObjectData {
   fn event(
     self: Arc<Self>,
@@ -87,3 +139,39 @@ impl ObjectData for DummyObjectData {
     // Do nothing
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn grow_keeps_contents() {
+    let mut memory = SealedMemory::create(16).unwrap();
+    memory.mmap.copy_from_slice(&[7; 16]);
+    for size in [64, 4096, 3 * 4096 + 5] {
+      assert!(memory.grow(size).unwrap());
+      assert_eq!(memory.mmap.len(), size);
+      assert_eq!(memory.file.metadata().unwrap().len(), size as u64);
+      assert_eq!(memory.mmap[..16], [7; 16]);
+      memory.mmap[size - 1] = 9;
+    }
+    assert_eq!(memory.mmap[4095], 9);
+    assert!(!memory.grow(64).unwrap());
+    assert_eq!(memory.len, 3 * 4096 + 5);
+  }
+
+  #[test]
+  fn shrinking_is_sealed() {
+    let memory = SealedMemory::create(4096).unwrap();
+    let err = memory.file.set_len(1024).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EPERM));
+    assert_eq!(memory.file.metadata().unwrap().len(), 4096);
+  }
+
+  #[test]
+  fn short_file_is_not_mapped() {
+    let memory = SealedMemory::create(4096).unwrap();
+    let err = map(&memory.file, 8192).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+  }
+}
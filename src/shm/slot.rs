@@ -1,8 +1,13 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
+use std::rc::Rc;
 use std::sync::{
   atomic::{AtomicBool, Ordering},
   Arc,
 };
 
+use super::format;
 use super::raw::RawPool;
 use wayland_client::backend::ObjectData;
 use wayland_client::protocol::{wl_buffer, wl_shm, wl_surface};
@@ -11,11 +16,12 @@ use wayland_client::protocol::{wl_buffer, wl_shm, wl_surface};
 struct Dimensions {
   width: u32,
   height: u32,
+  format: wl_shm::Format,
 }
 
 impl Dimensions {
   fn stride(&self) -> u32 {
-    self.width * 4
+    format::stride(self.format, self.width)
   }
   fn len(&self) -> usize {
     (self.stride() * self.height) as usize
@@ -26,7 +32,8 @@ impl Dimensions {
 pub struct BufferSlot {
   wl_buffer: Option<wl_buffer::WlBuffer>,
   offset: usize,
-  len: usize,
+  /// Bytes reserved in the pool, at least the length of the current dimensions
+  capacity: usize,
   dimensions: Dimensions,
   busy: Arc<AtomicBool>,
 }
@@ -36,12 +43,18 @@ impl BufferSlot {
     Self {
       wl_buffer: None,
       offset,
-      len: dimensions.len(),
+      capacity: dimensions.len(),
       dimensions,
       busy: Arc::new(AtomicBool::new(false)),
     }
   }
 
+  /// Slot outside of any pool, to draw into plain memory in tests
+  #[cfg(test)]
+  pub fn detached(width: u32, height: u32, format: wl_shm::Format) -> Self {
+    Self::new(0, Dimensions { width, height, format })
+  }
+
   pub fn height(&self) -> u32 {
     self.dimensions.height
   }
@@ -51,6 +64,9 @@ impl BufferSlot {
   pub fn stride(&self) -> u32 {
     self.dimensions.stride()
   }
+  pub fn format(&self) -> wl_shm::Format {
+    self.dimensions.format
+  }
 
   fn init_buffer(&mut self, pool: &mut RawPool) {
     let data = BufferSlotData {
@@ -62,14 +78,14 @@ impl BufferSlot {
         self.dimensions.width.try_into().unwrap(),
         self.dimensions.height.try_into().unwrap(),
         self.dimensions.stride().try_into().unwrap(),
-        wl_shm::Format::Xrgb8888,
+        self.dimensions.format,
         Arc::new(data),
       )
     });
   }
 
   fn get_data<'a>(&self, pool: &'a mut RawPool) -> &'a mut [u8] {
-    &mut pool.mmap()[self.offset..][..self.len]
+    &mut pool.mmap()[self.offset..][..self.dimensions.len()]
   }
 
   pub fn attach_to_surface(&self, wl_surface: &wl_surface::WlSurface) {
@@ -79,7 +95,7 @@ impl BufferSlot {
   }
 
   fn transform(&mut self, dimensions: Dimensions) {
-    if !self.busy.load(Ordering::Relaxed) && dimensions.len() == self.len {
+    if !self.busy.load(Ordering::Relaxed) && dimensions.len() <= self.capacity {
       if let Some(b) = &self.wl_buffer {
         b.destroy()
       };
@@ -97,55 +113,198 @@ impl Drop for BufferSlot {
   }
 }
 
-pub struct BufferSlotPool {
+/// Idle slots are only reused for buffers at least this fraction of their capacity
+const REUSE_RATIO: usize = 2;
+
+#[derive(Debug)]
+pub enum PoolError {
+  /// Allocating a buffer would take the pool over its cap
+  Cap { requested: usize, cap: usize },
+  /// The shared memory could not be grown
+  Io(io::Error),
+}
+
+impl fmt::Display for PoolError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      PoolError::Cap { requested, cap } => write!(
+        f,
+        "a buffer of {} bytes does not fit in the shared memory cap of {} bytes",
+        requested, cap
+      ),
+      PoolError::Io(err) => write!(f, "failed to grow the shared memory: {}", err),
+    }
+  }
+}
+
+/// Ranges of the pool handed out to slots, freed ranges are kept in a free list
+#[derive(Default)]
+struct Allocator {
+  /// End of the highest allocated range
   len: usize,
+  /// Unused ranges below `len` as offset and length, sorted by offset
+  free: Vec<(usize, usize)>,
+}
+
+impl Allocator {
+  /// Reserve `len` bytes, first from the free list and then from the end as long as it stays within `cap`
+  fn allocate(&mut self, len: usize, cap: usize) -> Result<usize, PoolError> {
+    if let Some(i) = self.free.iter().position(|(_, free)| *free >= len) {
+      let (offset, free) = self.free[i];
+      if free == len {
+        self.free.remove(i);
+      } else {
+        self.free[i] = (offset + len, free - len);
+      }
+      return Ok(offset);
+    }
+    if self.len + len > cap {
+      return Err(PoolError::Cap { requested: len, cap });
+    }
+    let offset = self.len;
+    self.len += len;
+    Ok(offset)
+  }
+
+  /// Keep only the `used` ranges, given as offset and length, and free everything between them
+  fn rebuild(&mut self, mut used: Vec<(usize, usize)>) {
+    used.sort_unstable();
+    self.free.clear();
+    let mut end = 0;
+    for (offset, len) in used {
+      if offset > end {
+        self.free.push((end, offset - end));
+      }
+      end = offset + len;
+    }
+    self.len = end;
+  }
+
+  /// Whether the free list holds more than the slots use, or the slots use under a quarter of `backing` bytes
+  fn is_wasteful(&self, backing: usize) -> bool {
+    let free: usize = self.free.iter().map(|(_, len)| len).sum();
+    let used = self.len - free;
+    free > used || used * 4 < backing
+  }
+}
+
+/// Slots of several sizes sharing one memfd
+pub struct BufferSlotPool {
+  allocator: Allocator,
+  initial_len: usize,
+  cap: usize,
+  wl_shm: wl_shm::WlShm,
   inner: RawPool,
   buffers: Vec<BufferSlot>,
 }
 
 impl BufferSlotPool {
-  pub fn create(len: usize, wl_shm: &wl_shm::WlShm) -> Self {
-    let pool = RawPool::create(len, wl_shm);
-    Self {
-      len: 0,
+  pub fn create(len: usize, cap: usize, wl_shm: &wl_shm::WlShm) -> io::Result<Self> {
+    let pool = RawPool::create(len, wl_shm)?;
+    Ok(Self {
+      allocator: Allocator::default(),
+      initial_len: len,
+      cap,
+      wl_shm: wl_shm.clone(),
       inner: pool,
       buffers: Vec::new(),
+    })
+  }
+
+  /// Reserve `len` bytes within `cap`, growing the memfd when the range ends past it
+  fn allocate(&mut self, len: usize, cap: usize) -> Result<usize, PoolError> {
+    let offset = self.allocator.allocate(len, cap)?;
+    if self.allocator.len > self.inner.len() {
+      let new_len = std::cmp::max(self.inner.len() * 2, self.allocator.len).min(cap);
+      if let Err(err) = self.inner.resize(new_len) {
+        // Give the range back, nothing may be placed past the end of the memfd
+        self.allocator.len = offset;
+        return Err(PoolError::Io(err));
+      }
     }
+    Ok(offset)
   }
 
-  fn push(&mut self, dimensions: Dimensions) -> usize {
-    let slot = BufferSlot::new(self.len, dimensions);
-    if self.len + slot.len > self.inner.len() {
-      let new_len = std::cmp::max(self.len * 2, self.len + slot.len);
-      self.inner.resize(new_len);
+  fn push(&mut self, dimensions: Dimensions, cap: usize) -> Result<usize, PoolError> {
+    let offset = match self.allocate(dimensions.len(), cap) {
+      Ok(offset) => offset,
+      Err(_) => {
+        // Make room from the idle slots before giving up
+        self.compact();
+        self.allocate(dimensions.len(), cap)?
+      }
+    };
+    self.buffers.push(BufferSlot::new(offset, dimensions));
+    Ok(self.buffers.len() - 1)
+  }
+
+  /// Free the slots the compositor has released and rebuild the free list from the ones in use.
+  /// The pool is recreated at its initial size once nothing uses it
+  pub fn compact(&mut self) {
+    self.buffers.retain(|b| b.busy.load(Ordering::Relaxed));
+    self
+      .allocator
+      .rebuild(self.buffers.iter().map(|b| (b.offset, b.capacity)).collect());
+    if self.buffers.is_empty() && self.inner.len() > self.initial_len {
+      match RawPool::create(self.initial_len, &self.wl_shm) {
+        Ok(pool) => self.inner = pool,
+        Err(err) => eprintln!("Failed to shrink the shared memory: {}", err),
+      }
     }
-    self.len += slot.len;
-    self.buffers.push(slot);
-    self.buffers.len() - 1
-  }
-
-  pub fn get_next_buffer(&mut self, width: u32, height: u32) -> (&mut BufferSlot, &mut [u8]) {
-    let dimensions = Dimensions { width, height };
-    // Search for existing buffer that can be used
-    let mut buffer_index: Option<usize> = None;
-    for i in 0..self.buffers.len() {
-      let b = &mut self.buffers[i];
-      if !b.busy.load(Ordering::Relaxed) {
-        if b.dimensions == dimensions {
-          // Buffer with same dimensions found, reuse it
-          buffer_index = Some(i);
-          break;
-        } else if b.len == dimensions.len() {
-          // Buffer with same length found, transform dimensions to fit
-          b.transform(dimensions);
-          buffer_index = Some(i);
-          break;
-        }
-      };
+  }
+
+  /// Compact only when fragmentation or an oversized memfd wastes memory, the idle spare slots of
+  /// double buffering stay otherwise
+  pub fn compact_if_wasteful(&mut self) {
+    if self.allocator.is_wasteful(self.inner.len()) {
+      self.compact();
     }
+  }
 
-    let buffer_index = buffer_index.unwrap_or_else(|| self.push(dimensions));
-    let buffer = &mut self.buffers[buffer_index];
+  /// Buffer of `format` cleared to zero, which is transparent for formats with alpha
+  pub fn get_next_buffer(
+    &mut self,
+    width: u32,
+    height: u32,
+    format: wl_shm::Format,
+  ) -> Result<(&mut BufferSlot, &mut [u8]), PoolError> {
+    let index = self.slot_index(Dimensions { width, height, format }, self.cap)?;
+    Ok(self.prepare(index))
+  }
+
+  /// Like `get_next_buffer`, but going over the cap rather than failing on it. For the background, without which
+  /// the lock surface stays unmapped
+  pub fn get_required_buffer(
+    &mut self,
+    width: u32,
+    height: u32,
+    format: wl_shm::Format,
+  ) -> Result<(&mut BufferSlot, &mut [u8]), PoolError> {
+    let dimensions = Dimensions { width, height, format };
+    let index = match self.slot_index(dimensions, self.cap) {
+      Ok(index) => index,
+      Err(err @ PoolError::Cap { .. }) => {
+        eprintln!("{}, going over it to cover the output", err);
+        self.slot_index(dimensions, usize::MAX)?
+      }
+      Err(err) => return Err(err),
+    };
+    Ok(self.prepare(index))
+  }
+
+  /// Index of an idle slot transformed to `dimensions`, or of a new one allocated within `cap`
+  fn slot_index(&mut self, dimensions: Dimensions, cap: usize) -> Result<usize, PoolError> {
+    match pick_slot(&self.buffers, dimensions) {
+      Some(i) => {
+        self.buffers[i].transform(dimensions);
+        Ok(i)
+      }
+      None => self.push(dimensions, cap),
+    }
+  }
+
+  fn prepare(&mut self, index: usize) -> (&mut BufferSlot, &mut [u8]) {
+    let buffer = &mut self.buffers[index];
     buffer.init_buffer(&mut self.inner);
     let data = buffer.get_data(&mut self.inner);
     data.fill(0);
@@ -153,6 +312,26 @@ impl BufferSlotPool {
   }
 }
 
+/// Idle slot to reuse for `dimensions`: one with the same dimensions, or else the smallest one large enough
+fn pick_slot(buffers: &[BufferSlot], dimensions: Dimensions) -> Option<usize> {
+  let len = dimensions.len();
+  let idle = |b: &BufferSlot| !b.busy.load(Ordering::Relaxed);
+  buffers
+    .iter()
+    .position(|b| idle(b) && b.dimensions == dimensions)
+    .or_else(|| {
+      buffers
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| idle(b) && b.capacity >= len && b.capacity <= len * REUSE_RATIO)
+        .min_by_key(|(_, b)| b.capacity)
+        .map(|(i, _)| i)
+    })
+}
+
+/// Pool shared by the surfaces of every output
+pub type SharedPool = Rc<RefCell<BufferSlotPool>>;
+
 struct BufferSlotData {
   busy: Arc<AtomicBool>,
 }
@@ -171,3 +350,113 @@ impl ObjectData for BufferSlotData {
 
   fn destroyed(&self, _object_id: wayland_client::backend::ObjectId) {}
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const CAP: usize = 1000;
+
+  fn dimensions(width: u32, height: u32) -> Dimensions {
+    Dimensions {
+      width,
+      height,
+      format: wl_shm::Format::Argb8888,
+    }
+  }
+
+  fn slot(offset: usize, width: u32, height: u32, busy: bool) -> BufferSlot {
+    let slot = BufferSlot::new(offset, dimensions(width, height));
+    slot.busy.store(busy, Ordering::Relaxed);
+    slot
+  }
+
+  #[test]
+  fn allocates_from_the_end() {
+    let mut allocator = Allocator::default();
+    assert_eq!(allocator.allocate(100, CAP).unwrap(), 0);
+    assert_eq!(allocator.allocate(50, CAP).unwrap(), 100);
+    assert_eq!(allocator.len, 150);
+  }
+
+  #[test]
+  fn free_list_split_and_exact_fit() {
+    let mut allocator = Allocator {
+      len: 400,
+      free: vec![(0, 100), (200, 50)],
+    };
+    // Split the first range large enough
+    assert_eq!(allocator.allocate(60, CAP).unwrap(), 0);
+    assert_eq!(allocator.free, vec![(60, 40), (200, 50)]);
+    // Exact fit removes the range
+    assert_eq!(allocator.allocate(50, CAP).unwrap(), 200);
+    assert_eq!(allocator.free, vec![(60, 40)]);
+    // Nothing fits, so the pool grows
+    assert_eq!(allocator.allocate(70, CAP).unwrap(), 400);
+    assert_eq!(allocator.len, 470);
+  }
+
+  #[test]
+  fn cap_exceeded() {
+    let mut allocator = Allocator::default();
+    allocator.allocate(900, CAP).unwrap();
+    let Err(PoolError::Cap { requested, cap }) = allocator.allocate(101, CAP) else {
+      panic!("expected the cap to be exceeded");
+    };
+    assert_eq!((requested, cap), (101, CAP));
+    assert_eq!(allocator.len, 900);
+    // The free list is still used when the end is full
+    allocator.free.push((0, 200));
+    assert_eq!(allocator.allocate(150, CAP).unwrap(), 0);
+  }
+
+  #[test]
+  fn rebuild_around_busy_slots() {
+    let mut allocator = Allocator {
+      len: 1000,
+      free: vec![(300, 100)],
+    };
+    allocator.rebuild(vec![(600, 100), (100, 200)]);
+    assert_eq!(allocator.free, vec![(0, 100), (300, 300)]);
+    assert_eq!(allocator.len, 700);
+    allocator.rebuild(Vec::new());
+    assert!(allocator.free.is_empty());
+    assert_eq!(allocator.len, 0);
+  }
+
+  #[test]
+  fn wasteful() {
+    let allocator = Allocator {
+      len: 1000,
+      free: vec![(0, 100)],
+    };
+    assert!(!allocator.is_wasteful(1000));
+    // The memfd grew far past what is in use
+    assert!(allocator.is_wasteful(4000));
+    let fragmented = Allocator {
+      len: 1000,
+      free: vec![(0, 300), (500, 300)],
+    };
+    assert!(fragmented.is_wasteful(1000));
+  }
+
+  #[test]
+  fn reuse_across_sizes() {
+    let slots = vec![
+      slot(0, 10, 10, false),
+      slot(400, 20, 10, false),
+      slot(1200, 10, 10, true),
+      slot(1600, 6, 10, false),
+    ];
+    // Same dimensions first
+    assert_eq!(pick_slot(&slots, dimensions(20, 10)), Some(1));
+    // Smallest idle slot at most twice the size
+    assert_eq!(pick_slot(&slots, dimensions(5, 10)), Some(3));
+    assert_eq!(pick_slot(&slots, dimensions(10, 8)), Some(0));
+    // Larger than every idle slot, or too small to waste one on
+    assert_eq!(pick_slot(&slots, dimensions(30, 10)), None);
+    assert_eq!(pick_slot(&slots, dimensions(2, 10)), None);
+    // Busy slots are never reused
+    assert_eq!(pick_slot(&slots[2..3], dimensions(10, 10)), None);
+  }
+}
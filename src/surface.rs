@@ -1,53 +1,117 @@
-use wayland_client::protocol::{wl_compositor, wl_shm, wl_subcompositor, wl_subsurface, wl_surface};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::Instant;
+
+use wayland_client::protocol::{
+  wl_callback, wl_compositor, wl_region, wl_shm, wl_subcompositor, wl_subsurface, wl_surface,
+};
 use wayland_client::Dispatch;
 use wayland_client::QueueHandle;
 
-use crate::args::Color;
-use crate::render::background::draw_background;
-use crate::render::clock::draw_clock;
-use crate::render::indicator::{draw_indicator, INDICATOR_BLOCK_COUNT};
-use crate::shm::slot::BufferSlotPool;
+use crate::layout::Placement;
+use crate::render::background::{draw_background, Background};
+use crate::render::buffer_context;
+use crate::render::effects::ImageData;
+use crate::shm::format::ShmFormats;
+use crate::shm::slot::{BufferSlot, SharedPool};
+use crate::widget::{Widget, WidgetContext, WidgetEntry};
+
+struct WidgetSurface {
+  widget: Box<dyn Widget>,
+  placement: Option<Placement>,
+  surface: wl_surface::WlSurface,
+  subsurface: wl_subsurface::WlSubsurface,
+  position: Option<(i32, i32)>,
+  width: u32,
+  height: u32,
+  next_refresh: Option<Instant>,
+  content: Option<WidgetContent>,
+}
+
+/// Hashes of the pixel rows last committed on a widget surface
+struct WidgetContent {
+  size: (u32, u32),
+  rows: Vec<u64>,
+}
+
+impl WidgetSurface {
+  fn is_due(&self, now: Instant) -> bool {
+    self.widget.refresh_interval().is_some() && self.next_refresh.is_none_or(|next| next <= now)
+  }
+}
+
+/// Widgets changed by an animation frame
+#[derive(Clone, Copy)]
+pub enum FrameScope {
+  /// Fades and the darkening overlay reach every widget
+  All,
+  /// Dimming without darkening only fades the widgets hidden when idle
+  Dimming,
+  State,
+}
 
 pub struct AppSurface {
-  pool: BufferSlotPool,
+  pool: SharedPool,
   width: u32,
   height: u32,
   base_surface: wl_surface::WlSurface,
-  clock_surface: wl_surface::WlSurface,
-  clock_subsurface: wl_subsurface::WlSubsurface,
-  clock_height: u32,
-  clock_width: u32,
-  indicator_surface: wl_surface::WlSurface,
-  indicator_subsurface: wl_subsurface::WlSubsurface,
+  background_image: Option<cairo::ImageSurface>,
+  background_cache: Option<(((u32, u32), wl_shm::Format), Vec<u8>)>,
+  widgets: Vec<WidgetSurface>,
+  frame_pending: bool,
 }
 
 impl AppSurface {
   pub fn create<D>(
     qh: &QueueHandle<D>,
-    wl_shm: &wl_shm::WlShm,
+    pool: &SharedPool,
     wl_compositor: &wl_compositor::WlCompositor,
     wl_subcompositor: &wl_subcompositor::WlSubcompositor,
+    widgets: Vec<WidgetEntry>,
   ) -> Self
   where
     D: 'static + Dispatch<wl_surface::WlSurface, ()>,
     D: 'static + Dispatch<wl_subsurface::WlSubsurface, ()>,
+    D: 'static + Dispatch<wl_region::WlRegion, ()>,
   {
     let base_surface = wl_compositor.create_surface(qh, ());
-    let clock_surface = wl_compositor.create_surface(qh, ());
-    let clock_subsurface = wl_subcompositor.get_subsurface(&clock_surface, &base_surface, qh, ());
-    let indicator_surface = wl_compositor.create_surface(qh, ());
-    let indicator_subsurface = wl_subcompositor.get_subsurface(&indicator_surface, &base_surface, qh, ());
+    // The background is always opaque, the region is clipped to the surface size
+    let opaque = wl_compositor.create_region(qh, ());
+    opaque.add(0, 0, i32::MAX, i32::MAX);
+    base_surface.set_opaque_region(Some(&opaque));
+    opaque.destroy();
+    let widgets = create_widget_surfaces(qh, wl_compositor, wl_subcompositor, &base_surface, widgets);
     Self {
-      pool: BufferSlotPool::create(4096, wl_shm),
+      pool: pool.clone(),
       width: 0,
       height: 0,
       base_surface,
-      clock_surface,
-      clock_subsurface,
-      clock_width: 0,
-      clock_height: 0,
-      indicator_surface,
-      indicator_subsurface,
+      background_image: None,
+      background_cache: None,
+      widgets,
+      frame_pending: false,
+    }
+  }
+
+  /// Replace every widget, their surfaces are recreated on top of the background
+  pub fn set_widgets<D>(
+    &mut self,
+    qh: &QueueHandle<D>,
+    wl_compositor: &wl_compositor::WlCompositor,
+    wl_subcompositor: &wl_subcompositor::WlSubcompositor,
+    widgets: Vec<WidgetEntry>,
+  ) where
+    D: 'static + Dispatch<wl_surface::WlSurface, ()>,
+    D: 'static + Dispatch<wl_subsurface::WlSubsurface, ()>,
+  {
+    self.destroy_widgets();
+    self.widgets = create_widget_surfaces(qh, wl_compositor, wl_subcompositor, &self.base_surface, widgets);
+  }
+
+  fn destroy_widgets(&mut self) {
+    for widget in self.widgets.drain(..).rev() {
+      widget.subsurface.destroy();
+      widget.surface.destroy();
     }
   }
 
@@ -55,99 +119,297 @@ impl AppSurface {
     if width != 0 && height != 0 && self.width != width && self.height != height {
       self.width = width;
       self.height = height;
-      self.clock_width = width;
-      self.clock_height = height;
     }
   }
 
-  pub fn render_bg(&mut self, color: Color) {
+  /// Image drawn over the background colour, scaled to cover the output
+  pub fn set_background_image(&mut self, image: Option<cairo::ImageSurface>) {
+    self.background_image = image;
+    self.background_cache = None;
+  }
+
+  /// Draw the background from scratch next time, after its options changed
+  pub fn invalidate_background(&mut self) {
+    self.background_cache = None;
+  }
+
+  /// Draw the background, darkened by a black overlay of `darken` opacity
+  pub fn render_bg(&mut self, background: &Background, formats: &ShmFormats, darken: f64) {
     if self.width == 0 || self.height == 0 {
       return;
     }
-    let buffer = draw_background(&mut self.pool, self.width, self.height, color);
+    let dimensions = (self.width, self.height);
+    let format = formats.background(background.wants_deep_color());
+    let mut pool = self.pool.borrow_mut();
+    let (buffer, data) = match pool.get_required_buffer(self.width, self.height, format) {
+      Ok(buffer) => buffer,
+      Err(err) => {
+        eprintln!("Failed to allocate background buffer: {}", err);
+        return;
+      }
+    };
+    match self.background_cache.as_ref() {
+      Some((cached, pixels)) if *cached == (dimensions, format) => data.copy_from_slice(pixels),
+      _ => {
+        draw_background(buffer, data, background, self.background_image.as_ref());
+        let mut image = ImageData {
+          data: &mut *data,
+          width: buffer.width() as usize,
+          height: buffer.height() as usize,
+          stride: buffer.stride() as usize,
+        };
+        for effect in background.effects.iter() {
+          image.apply(*effect);
+        }
+        if background.is_cached() {
+          // Gradients, patterns and effects are expensive, only draw them once per output size
+          self.background_cache = Some(((dimensions, format), data.to_vec()));
+        }
+      }
+    }
+    if darken > 0.0 {
+      let context = buffer_context(buffer, data);
+      context.set_source_rgba(0.0, 0.0, 0.0, darken);
+      context.paint().unwrap();
+    }
     buffer.attach_to_surface(&self.base_surface);
-    self.base_surface.damage(0, 0, i32::MAX, i32::MAX);
+    self.base_surface.damage_buffer(0, 0, self.width as i32, self.height as i32);
     self.base_surface.commit();
   }
 
-  pub fn render_clock(&mut self, text_color: Color, font: String, font_size: f64, bg_color: Color) {
-    if self.clock_width == 0 || self.clock_height == 0 {
-      return;
+  /// Redraw every widget
+  pub fn render_widgets(&mut self, ctx: &WidgetContext) {
+    self.render_widgets_where(ctx, |_| true);
+  }
+
+  /// Redraw the widgets that depend on the application state
+  pub fn render_state(&mut self, ctx: &WidgetContext) {
+    self.render_widgets_where(ctx, |w| w.widget.reacts_to_state());
+  }
+
+  /// Redraw the widgets whose refresh interval has elapsed and return when the next one is due
+  pub fn render_due(&mut self, ctx: &WidgetContext) -> Option<Instant> {
+    let now = Instant::now();
+    self.render_widgets_where(ctx, |w| w.is_due(now));
+    self.widgets.iter().filter_map(|w| w.next_refresh).min()
+  }
+
+  /// Ask for a frame callback on the next commit unless one is already pending
+  pub fn schedule_frame<D>(&mut self, qh: &QueueHandle<D>)
+  where
+    D: 'static + Dispatch<wl_callback::WlCallback, wl_surface::WlSurface>,
+  {
+    if !self.frame_pending && self.width != 0 && self.height != 0 {
+      self.request_frame(qh);
+      self.base_surface.commit();
     }
-    let buffer = draw_clock(
-      &mut self.pool,
-      self.clock_width,
-      self.clock_height,
-      text_color,
-      font,
-      font_size,
-      bg_color,
-    );
-    buffer.attach_to_surface(&self.clock_surface);
-    self.clock_surface.damage(0, 0, i32::MAX, i32::MAX);
-    self.clock_surface.commit();
-
-    // Clock surface size changed, update position
-    if buffer.width() != self.clock_width || buffer.height() != self.clock_height {
-      self.clock_width = buffer.width();
-      self.clock_height = buffer.height();
-      let x = (self.width - self.clock_width) / 2;
-      let y = (self.height - self.clock_height) / 2;
-      self.clock_subsurface.set_position(x as i32, y as i32);
+  }
+
+  /// Redraw the widgets within `scope` when a frame callback is done, asking for the next frame while `animating`
+  pub fn render_frame<D>(&mut self, ctx: &WidgetContext, qh: &QueueHandle<D>, animating: bool, scope: FrameScope)
+  where
+    D: 'static + Dispatch<wl_callback::WlCallback, wl_surface::WlSurface>,
+  {
+    self.frame_pending = false;
+    if animating {
+      self.request_frame(qh);
     }
+    let committed = self.render_widgets_where(ctx, |w| match scope {
+      FrameScope::All => true,
+      FrameScope::Dimming => w.widget.hides_when_idle() || w.widget.reacts_to_state(),
+      FrameScope::State => w.widget.reacts_to_state(),
+    });
+    if animating && !committed {
+      self.base_surface.commit();
+    }
+  }
 
-    self.base_surface.commit();
+  fn request_frame<D>(&mut self, qh: &QueueHandle<D>)
+  where
+    D: 'static + Dispatch<wl_callback::WlCallback, wl_surface::WlSurface>,
+  {
+    self.base_surface.frame(qh, self.base_surface.clone());
+    self.frame_pending = true;
   }
 
-  pub fn render_indicator_input(
-    &mut self,
-    len: usize,
-    cursor_color: Color,
-    cursor_inc_color: Color,
-    trail_color: Color,
-    trail_inc_color: Color,
-    bg_color: Color,
-  ) {
-    if len == 0 {
-      self.render_indicator_full(trail_color, bg_color);
-      return;
+  /// Draw and commit the widgets matching `filter`, returning whether the base surface was committed
+  fn render_widgets_where(&mut self, ctx: &WidgetContext, filter: impl Fn(&WidgetSurface) -> bool) -> bool {
+    if self.width == 0 || self.height == 0 {
+      return false;
     }
-    let strength = (len - 1) / INDICATOR_BLOCK_COUNT;
-    let pos = (len - 1) % INDICATOR_BLOCK_COUNT;
-    let block_colors = std::array::from_fn(|i| {
-      if i < pos {
-        add_color(trail_color, trail_inc_color, strength + 1)
-      } else if i == pos {
-        add_color(cursor_color, cursor_inc_color, strength)
-      } else {
-        add_color(trail_color, trail_inc_color, strength)
+    let now = Instant::now();
+    let mut rendered = false;
+    for widget in self.widgets.iter_mut().filter(|w| filter(w)) {
+      if widget.is_due(now) {
+        widget.widget.refresh();
       }
-    });
-    self.render_indicator(block_colors, bg_color)
+      let (width, height) = widget.widget.measure(ctx, (self.width, self.height));
+      if let Some(interval) = widget.widget.refresh_interval() {
+        // Keep to the schedule when due, unless rendering fell behind it.
+        // Rendering early for another reason restarts the interval from now
+        let next = match widget.next_refresh {
+          Some(next) if next <= now => next + interval,
+          _ => now + interval,
+        };
+        widget.next_refresh = Some(if next <= now { now + interval } else { next });
+      }
+      let mut pool = self.pool.borrow_mut();
+      let (buffer, data) = match pool.get_next_buffer(width, height, ShmFormats::OVERLAY) {
+        Ok(buffer) => buffer,
+        Err(err) => {
+          // Keep showing the previous content
+          eprintln!("Failed to allocate widget buffer: {}", err);
+          continue;
+        }
+      };
+      let context = buffer_context(buffer, data);
+      draw_widget(widget.widget.as_mut(), ctx, buffer, &context);
+      drop(context);
+
+      // Skip the commit when the content did not change, only damage what did otherwise
+      let Some((x, y, w, h)) = update_content(&mut widget.content, (width, height), data, buffer.stride()) else {
+        continue;
+      };
+      buffer.attach_to_surface(&widget.surface);
+      widget.surface.damage_buffer(x as i32, y as i32, w as i32, h as i32);
+      widget.surface.commit();
+      widget.width = width;
+      widget.height = height;
+      rendered = true;
+    }
+    // Subsurface positions only apply with the next commit of the base surface
+    let moved = self.arrange(ctx.frame.shake);
+    if rendered || moved {
+      self.base_surface.commit();
+    }
+    rendered || moved
   }
 
-  pub fn render_indicator_full(&mut self, color: Color, bg_color: Color) {
-    self.render_indicator([color; INDICATOR_BLOCK_COUNT], bg_color);
+  /// Position every widget, stacking those without a placement below the previous one.
+  /// Widgets reacting to the state are moved by `shake`, returns whether any position changed
+  fn arrange(&mut self, shake: i32) -> bool {
+    let mut moved = false;
+    let output = (self.width, self.height);
+    let mut previous: Option<(i32, i32, u32, u32)> = None;
+    for widget in self.widgets.iter_mut() {
+      let size = (widget.width, widget.height);
+      let position = match (widget.placement, previous) {
+        (Some(placement), _) => placement.place(output, size),
+        (None, Some((x, y, width, height))) => {
+          let center = x + width as i32 / 2;
+          (center - size.0 as i32 / 2, y + height as i32 + 20)
+        }
+        (None, None) => Placement::default().place(output, size),
+      };
+      previous = Some((position.0, position.1, size.0, size.1));
+      let shaken = if widget.widget.reacts_to_state() {
+        (position.0 + shake, position.1)
+      } else {
+        position
+      };
+      if widget.position != Some(shaken) {
+        widget.subsurface.set_position(shaken.0, shaken.1);
+        widget.position = Some(shaken);
+        moved = true;
+      }
+    }
+    moved
   }
+}
 
-  fn render_indicator(&mut self, block_colors: [Color; INDICATOR_BLOCK_COUNT], bg_color: Color) {
-    let buffer = draw_indicator(&mut self.pool, block_colors, bg_color);
-    buffer.attach_to_surface(&self.indicator_surface);
-    self.indicator_surface.damage(0, 0, i32::MAX, i32::MAX);
-    self.indicator_surface.commit();
-    let x = (self.width - buffer.width()) / 2;
-    let y = (self.height - self.clock_height) / 2 + self.clock_height + 20;
-    self.indicator_subsurface.set_position(x as i32, y as i32);
-    self.base_surface.commit();
+fn create_widget_surfaces<D>(
+  qh: &QueueHandle<D>,
+  wl_compositor: &wl_compositor::WlCompositor,
+  wl_subcompositor: &wl_subcompositor::WlSubcompositor,
+  base_surface: &wl_surface::WlSurface,
+  widgets: Vec<WidgetEntry>,
+) -> Vec<WidgetSurface>
+where
+  D: 'static + Dispatch<wl_surface::WlSurface, ()>,
+  D: 'static + Dispatch<wl_subsurface::WlSubsurface, ()>,
+{
+  widgets
+    .into_iter()
+    .map(|(widget, placement)| {
+      let surface = wl_compositor.create_surface(qh, ());
+      let subsurface = wl_subcompositor.get_subsurface(&surface, base_surface, qh, ());
+      WidgetSurface {
+        widget,
+        placement,
+        surface,
+        subsurface,
+        position: None,
+        width: 0,
+        height: 0,
+        next_refresh: None,
+        content: None,
+      }
+    })
+    .collect()
+}
+
+/// Draw a widget faded by the animation frame and darkened by its overlay
+fn draw_widget(widget: &mut dyn Widget, ctx: &WidgetContext, buffer: &BufferSlot, context: &cairo::Context) {
+  let mut opacity = ctx.frame.opacity;
+  if widget.hides_when_idle() {
+    opacity *= 1.0 - ctx.frame.dim;
+  }
+  if opacity < 1.0 || ctx.frame.darken > 0.0 {
+    context.push_group();
+    widget.draw(ctx, buffer, context);
+    // Darken only where the widget drew something
+    context.set_operator(cairo::Operator::Atop);
+    context.set_source_rgba(0.0, 0.0, 0.0, ctx.frame.darken);
+    context.paint().unwrap();
+    context.pop_group_to_source().unwrap();
+    context.set_operator(cairo::Operator::Over);
+    context.paint_with_alpha(opacity).unwrap();
+  } else {
+    widget.draw(ctx, buffer, context);
   }
 }
 
+/// Remember `data` as the committed content and return the damaged area, None when nothing changed
+///
+/// Only row hashes are kept, so the damage spans whole rows from the first to the last changed one.
+fn update_content(
+  content: &mut Option<WidgetContent>,
+  size: (u32, u32),
+  data: &[u8],
+  stride: u32,
+) -> Option<(u32, u32, u32, u32)> {
+  let rows = row_hashes(data, size.0, size.1, stride);
+  let damage = match content.as_ref().filter(|previous| previous.size == size) {
+    Some(previous) => dirty_rows(&previous.rows, &rows).map(|(y, h)| (0, y, size.0, h))?,
+    None => (0, 0, size.0, size.1),
+  };
+  *content = Some(WidgetContent { size, rows });
+  Some(damage)
+}
+
+/// Hash of the visible pixels of every row, leaving out the stride padding
+fn row_hashes(data: &[u8], width: u32, height: u32, stride: u32) -> Vec<u64> {
+  let (width, stride) = (width as usize, stride as usize);
+  (0..height as usize)
+    .map(|y| {
+      let mut hasher = DefaultHasher::new();
+      data[y * stride..y * stride + width * 4].hash(&mut hasher);
+      hasher.finish()
+    })
+    .collect()
+}
+
+/// First row and number of rows between the first and the last row that differ
+fn dirty_rows(previous: &[u64], current: &[u64]) -> Option<(u32, u32)> {
+  let changed = |(a, b): (&u64, &u64)| a != b;
+  let first = previous.iter().zip(current).position(changed)?;
+  let last = previous.iter().zip(current).rposition(changed).unwrap();
+  Some((first as u32, (last - first + 1) as u32))
+}
+
 impl Drop for AppSurface {
   fn drop(&mut self) {
-    self.indicator_subsurface.destroy();
-    self.indicator_surface.destroy();
-    self.clock_subsurface.destroy();
-    self.clock_surface.destroy();
+    self.destroy_widgets();
     self.base_surface.destroy();
   }
 }
@@ -158,18 +420,161 @@ impl AsRef<wl_surface::WlSurface> for AppSurface {
   }
 }
 
-fn add_color(color: Color, inc_color: Color, strength: usize) -> Color {
-  Color {
-    r: color.r + inc_color.r * strength as f64,
-    g: color.g + inc_color.g * strength as f64,
-    b: color.b + inc_color.b * strength as f64,
-  }
-}
-
 #[macro_export]
 macro_rules! delegate_dispatch_surface {
   ($l: ty) => {
     wayland_client::delegate_noop!($l: ignore wayland_client::protocol::wl_surface::WlSurface);
     wayland_client::delegate_noop!($l: ignore wayland_client::protocol::wl_subsurface::WlSubsurface);
+    wayland_client::delegate_noop!($l: wayland_client::protocol::wl_region::WlRegion);
   };
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::animation::AnimationFrame;
+  use crate::application::AppState;
+  use crate::args::Args;
+  use crate::widget::clock::ClockWidget;
+  use chrono::{DateTime, Local, TimeZone};
+  use clap::Parser;
+  use std::cell::Cell;
+  use wayland_client::protocol::wl_shm;
+
+  const WIDTH: u32 = 4;
+  const HEIGHT: u32 = 3;
+  const STRIDE: u32 = 20;
+
+  fn set_pixel(data: &mut [u8], x: u32, y: u32) {
+    data[(y * STRIDE + x * 4) as usize] = 0xFF;
+  }
+
+  fn damage(previous: &[u8], current: &[u8]) -> Option<(u32, u32, u32, u32)> {
+    let mut content = None;
+    update_content(&mut content, (WIDTH, HEIGHT), previous, STRIDE);
+    update_content(&mut content, (WIDTH, HEIGHT), current, STRIDE)
+  }
+
+  #[test]
+  fn identical_content_has_no_damage() {
+    let previous = vec![7; (STRIDE * HEIGHT) as usize];
+    assert_eq!(damage(&previous, &previous.clone()), None);
+  }
+
+  #[test]
+  fn damage_ignores_stride_padding() {
+    let previous = vec![0; (STRIDE * HEIGHT) as usize];
+    let mut current = previous.clone();
+    current[(STRIDE - 1) as usize] = 0xFF;
+    assert_eq!(damage(&previous, &current), None);
+  }
+
+  #[test]
+  fn damage_covers_changed_row() {
+    let previous = vec![0; (STRIDE * HEIGHT) as usize];
+    let mut current = previous.clone();
+    set_pixel(&mut current, 2, 1);
+    assert_eq!(damage(&previous, &current), Some((0, 1, WIDTH, 1)));
+  }
+
+  #[test]
+  fn damage_spans_several_rows() {
+    let previous = vec![0; (STRIDE * HEIGHT) as usize];
+    let mut current = previous.clone();
+    set_pixel(&mut current, 3, 0);
+    set_pixel(&mut current, 1, 2);
+    assert_eq!(damage(&previous, &current), Some((0, 0, WIDTH, 3)));
+  }
+
+  #[test]
+  fn resized_content_is_damaged_entirely() {
+    let mut content = None;
+    let data = vec![0; (STRIDE * HEIGHT) as usize];
+    update_content(&mut content, (WIDTH, HEIGHT), &data, STRIDE);
+    let damage = update_content(&mut content, (WIDTH, 2), &data[..(STRIDE * 2) as usize], STRIDE);
+    assert_eq!(damage, Some((0, 0, WIDTH, 2)));
+  }
+
+  struct StubWidget {
+    red: f64,
+  }
+
+  impl Widget for StubWidget {
+    fn measure(&mut self, _ctx: &WidgetContext, _output: (u32, u32)) -> (u32, u32) {
+      (WIDTH, HEIGHT)
+    }
+
+    fn draw(&mut self, _ctx: &WidgetContext, _buffer: &BufferSlot, context: &cairo::Context) {
+      context.set_source_rgba(self.red, 0.0, 0.0, 1.0);
+      context.rectangle(0.0, 0.0, 2.0, 2.0);
+      context.fill().unwrap();
+    }
+  }
+
+  /// Draw the widget like a render does and return whether it would be committed
+  fn render(widget: &mut dyn Widget, content: &mut Option<WidgetContent>) -> bool {
+    let ctx = WidgetContext {
+      state: AppState::Idle,
+      previous_state: AppState::Idle,
+      password: "",
+      password_revealed: false,
+      grace: false,
+      notice: None,
+      frame: AnimationFrame::default(),
+    };
+    let (width, height) = widget.measure(&ctx, (1920, 1080));
+    let buffer = BufferSlot::detached(width, height, wl_shm::Format::Argb8888);
+    let mut data = vec![0; (buffer.stride() * height) as usize];
+    let context = buffer_context(&buffer, &mut data);
+    draw_widget(widget, &ctx, &buffer, &context);
+    drop(context);
+    update_content(content, (width, height), &data, buffer.stride()).is_some()
+  }
+
+  #[test]
+  fn unchanged_widget_is_not_committed() {
+    let mut widget = StubWidget { red: 1.0 };
+    let mut content = None;
+    let commits = (0..60).filter(|_| render(&mut widget, &mut content)).count();
+    assert_eq!(commits, 1);
+
+    widget.red = 0.5;
+    assert!(render(&mut widget, &mut content));
+    assert!(!render(&mut widget, &mut content));
+  }
+
+  thread_local! {
+    /// Milliseconds since the start of the simulated minute
+    static ELAPSED: Cell<i64> = const { Cell::new(0) };
+  }
+
+  fn simulated_now() -> DateTime<Local> {
+    let elapsed = ELAPSED.with(Cell::get);
+    Local.timestamp_millis_opt(1_700_000_000_000 + elapsed).unwrap()
+  }
+
+  /// Base surface and static widget commits over a minute of renders every `step_ms`
+  fn commits_per_minute(step_ms: i64) -> (usize, usize) {
+    let args = Args::try_parse_from(["minlock"]).unwrap();
+    let mut clock = ClockWidget::with_clock(&args, simulated_now);
+    let mut stub = StubWidget { red: 1.0 };
+    let (mut clock_content, mut stub_content) = (None, None);
+    let (mut base_commits, mut stub_commits) = (0, 0);
+    for tick in 0..60_000 / step_ms {
+      ELAPSED.with(|elapsed| elapsed.set(tick * step_ms));
+      let clock_committed = render(&mut clock, &mut clock_content);
+      let stub_committed = render(&mut stub, &mut stub_content);
+      // The base surface is committed whenever one of its widgets is
+      base_commits += usize::from(clock_committed || stub_committed);
+      stub_commits += usize::from(stub_committed);
+    }
+    (base_commits, stub_commits)
+  }
+
+  #[test]
+  fn ticking_clock_commits_once_per_second() {
+    assert_eq!(commits_per_minute(1000), (60, 1));
+    // Renders within the same second draw the same text and are skipped
+    assert_eq!(commits_per_minute(250), (60, 1));
+  }
+}
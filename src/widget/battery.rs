@@ -0,0 +1,239 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::{Widget, WidgetContext};
+use crate::args::{Args, Color};
+use crate::render::text::{draw_text, measure_text, TextMetrics, TextStyle};
+use crate::shm::slot::BufferSlot;
+
+const ICON_SPACING: f64 = 8.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct PowerStatus {
+  /// Average capacity of all batteries in percent
+  pub capacity: u32,
+  pub charging: bool,
+  pub ac_online: bool,
+}
+
+/// Read the power supply state from a sysfs `power_supply` class directory
+pub fn read_power_status(root: &Path) -> Option<PowerStatus> {
+  let read = |dir: &Path, name: &str| fs::read_to_string(dir.join(name)).ok().map(|v| v.trim().to_owned());
+  let mut capacities = Vec::new();
+  let mut charging = false;
+  let mut ac_online = false;
+  for entry in fs::read_dir(root).ok()?.flatten() {
+    let dir = entry.path();
+    match read(&dir, "type").as_deref() {
+      Some("Battery") => {
+        if let Some(capacity) = read(&dir, "capacity").and_then(|v| v.parse::<u32>().ok()) {
+          capacities.push(capacity.min(100));
+        }
+        charging |= read(&dir, "status").as_deref() == Some("Charging");
+      }
+      Some("Mains") => {
+        ac_online |= read(&dir, "online").as_deref() == Some("1");
+      }
+      _ => {}
+    }
+  }
+  if capacities.is_empty() {
+    return None;
+  }
+  Some(PowerStatus {
+    capacity: capacities.iter().sum::<u32>() / capacities.len() as u32,
+    charging,
+    ac_online,
+  })
+}
+
+pub struct BatteryWidget {
+  sysfs_root: PathBuf,
+  style: TextStyle,
+  low_color: Color,
+  low_threshold: u32,
+  refresh_interval: Duration,
+  /// Read again only when the refresh interval elapsed, not for every fade frame
+  status: Option<PowerStatus>,
+  text: String,
+  metrics: Option<TextMetrics>,
+}
+
+impl BatteryWidget {
+  pub fn new(args: &Args) -> Self {
+    Self {
+      sysfs_root: args.battery_sysfs_root.clone(),
+      style: TextStyle::new(args.battery_color, &args.battery_font, args.battery_font_size),
+      low_color: args.battery_low_color,
+      low_threshold: args.battery_low_threshold,
+      refresh_interval: Duration::from_secs(args.battery_refresh),
+      status: None,
+      text: String::new(),
+      metrics: None,
+    }
+  }
+
+  fn icon_width(height: u32) -> f64 {
+    height as f64 * 1.6
+  }
+}
+
+impl Widget for BatteryWidget {
+  fn measure(&mut self, _ctx: &WidgetContext, _output: (u32, u32)) -> (u32, u32) {
+    let Some(status) = self.status else {
+      // Nothing to show on machines without a battery
+      self.metrics = None;
+      return (1, 1);
+    };
+    self.text = format!("{}%", status.capacity);
+    let metrics = measure_text(&self.style, &self.text);
+    self.metrics = Some(metrics);
+    let width = Self::icon_width(metrics.height) + ICON_SPACING + metrics.width as f64;
+    (width.ceil() as u32, metrics.height)
+  }
+
+  fn draw(&mut self, _ctx: &WidgetContext, _buffer: &BufferSlot, context: &cairo::Context) {
+    let (Some(status), Some(metrics)) = (self.status, self.metrics) else {
+      return;
+    };
+    let low = status.capacity <= self.low_threshold && !status.charging && !status.ac_online;
+    let color = if low { self.low_color } else { self.style.color };
+
+    // Battery body with a terminal on the right, filled up to the capacity
+    let height = metrics.height as f64;
+    let icon_width = Self::icon_width(metrics.height);
+    let line = (height / 12.0).max(1.0);
+    let body_height = height * 0.6;
+    let body_width = icon_width - line * 3.0;
+    let top = (height - body_height) / 2.0;
+    context.set_source_rgba(color.r, color.g, color.b, color.a);
+    context.set_line_width(line);
+    context.rectangle(line / 2.0, top + line / 2.0, body_width - line, body_height - line);
+    context.stroke().unwrap();
+    context.rectangle(body_width, top + body_height * 0.3, line * 2.0, body_height * 0.4);
+    context.fill().unwrap();
+    let fill_width = (body_width - line * 4.0) * status.capacity as f64 / 100.0;
+    context.rectangle(line * 2.0, top + line * 2.0, fill_width, body_height - line * 4.0);
+    context.fill().unwrap();
+
+    if status.charging || status.ac_online {
+      // Lightning bolt cut out of the battery body
+      let cx = body_width / 2.0;
+      let cy = height / 2.0;
+      let h = body_height * 0.8;
+      context.move_to(cx + h * 0.1, cy - h / 2.0);
+      context.line_to(cx - h * 0.25, cy + h * 0.05);
+      context.line_to(cx, cy + h * 0.05);
+      context.line_to(cx - h * 0.1, cy + h / 2.0);
+      context.line_to(cx + h * 0.25, cy - h * 0.05);
+      context.line_to(cx, cy - h * 0.05);
+      context.close_path();
+      context.set_operator(cairo::Operator::Clear);
+      context.fill_preserve().unwrap();
+      context.set_operator(cairo::Operator::Over);
+      context.set_source_rgba(color.r, color.g, color.b, color.a);
+      context.set_line_width(line / 2.0);
+      context.stroke().unwrap();
+    }
+
+    let style = TextStyle {
+      color,
+      ..self.style.clone()
+    };
+    draw_text(context, &style, &self.text, icon_width + ICON_SPACING, 0.0);
+  }
+
+  fn refresh_interval(&self) -> Option<Duration> {
+    Some(self.refresh_interval)
+  }
+
+  fn refresh(&mut self) {
+    self.status = read_power_status(&self.sysfs_root);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Fake `power_supply` class directory, removed again when dropped
+  struct FakeSysfs(PathBuf);
+
+  impl FakeSysfs {
+    fn new(name: &str) -> Self {
+      let root = std::env::temp_dir().join(format!("minlock-{}-{}", name, std::process::id()));
+      let _ = fs::remove_dir_all(&root);
+      fs::create_dir_all(&root).unwrap();
+      Self(root)
+    }
+
+    fn supply(&self, name: &str, attributes: &[(&str, &str)]) -> &Self {
+      let dir = self.0.join(name);
+      fs::create_dir_all(&dir).unwrap();
+      for (attribute, value) in attributes {
+        fs::write(dir.join(attribute), format!("{}\n", value)).unwrap();
+      }
+      self
+    }
+  }
+
+  impl Drop for FakeSysfs {
+    fn drop(&mut self) {
+      let _ = fs::remove_dir_all(&self.0);
+    }
+  }
+
+  #[test]
+  fn averages_batteries() {
+    let sysfs = FakeSysfs::new("average");
+    sysfs
+      .supply(
+        "BAT0",
+        &[("type", "Battery"), ("capacity", "80"), ("status", "Discharging")],
+      )
+      .supply(
+        "BAT1",
+        &[("type", "Battery"), ("capacity", "40"), ("status", "Discharging")],
+      );
+    let status = read_power_status(&sysfs.0).unwrap();
+    assert_eq!(status.capacity, 60);
+    assert!(!status.charging);
+    assert!(!status.ac_online);
+  }
+
+  #[test]
+  fn reads_charging_status() {
+    let sysfs = FakeSysfs::new("charging");
+    sysfs.supply(
+      "BAT0",
+      &[("type", "Battery"), ("capacity", "50"), ("status", "Charging")],
+    );
+    assert!(read_power_status(&sysfs.0).unwrap().charging);
+  }
+
+  #[test]
+  fn reads_mains_online() {
+    let sysfs = FakeSysfs::new("mains");
+    sysfs
+      .supply("AC", &[("type", "Mains"), ("online", "1")])
+      .supply("BAT0", &[("type", "Battery"), ("capacity", "100"), ("status", "Full")]);
+    let status = read_power_status(&sysfs.0).unwrap();
+    assert!(status.ac_online);
+    assert!(!status.charging);
+  }
+
+  #[test]
+  fn no_battery() {
+    let sysfs = FakeSysfs::new("none");
+    sysfs.supply("AC", &[("type", "Mains"), ("online", "1")]);
+    assert!(read_power_status(&sysfs.0).is_none());
+  }
+
+  #[test]
+  fn clamps_capacity() {
+    let sysfs = FakeSysfs::new("clamp");
+    sysfs.supply("BAT0", &[("type", "Battery"), ("capacity", "104"), ("status", "Full")]);
+    assert_eq!(read_power_status(&sysfs.0).unwrap().capacity, 100);
+  }
+}
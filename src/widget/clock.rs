@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+
+use super::{Widget, WidgetContext};
+use crate::args::Args;
+use crate::render::text::{draw_text, measure_text, TextMetrics, TextStyle};
+use crate::shm::slot::BufferSlot;
+
+pub struct ClockWidget {
+  style: TextStyle,
+  text: String,
+  metrics: Option<TextMetrics>,
+  width: u32,
+  /// Source of the current time, replaced in tests
+  now: fn() -> DateTime<Local>,
+}
+
+impl ClockWidget {
+  pub fn new(args: &Args) -> Self {
+    Self::with_clock(args, Local::now)
+  }
+
+  pub(crate) fn with_clock(args: &Args, now: fn() -> DateTime<Local>) -> Self {
+    Self {
+      style: TextStyle::new(args.clock_color, &args.clock_font, args.clock_font_size),
+      text: String::new(),
+      metrics: None,
+      width: 0,
+      now,
+    }
+  }
+}
+
+impl Widget for ClockWidget {
+  fn measure(&mut self, _ctx: &WidgetContext, _output: (u32, u32)) -> (u32, u32) {
+    self.text = (self.now)().format("%H:%M:%S").to_string();
+    let metrics = measure_text(&self.style, &self.text);
+    // Never shrink so the clock does not jitter as digits change width
+    self.width = std::cmp::max(self.width, metrics.width);
+    self.metrics = Some(metrics);
+    (self.width, metrics.height)
+  }
+
+  fn draw(&mut self, _ctx: &WidgetContext, buffer: &BufferSlot, context: &cairo::Context) {
+    let metrics = self.metrics.unwrap();
+    let x = (buffer.width() - metrics.width) / 2;
+    draw_text(context, &self.style, &self.text, x as f64, 0.0);
+  }
+
+  fn refresh_interval(&self) -> Option<Duration> {
+    Some(Duration::from_secs(1))
+  }
+
+  fn hides_when_idle(&self) -> bool {
+    true
+  }
+}
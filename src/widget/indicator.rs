@@ -0,0 +1,63 @@
+use super::{Widget, WidgetContext};
+use crate::application::AppState;
+use crate::args::{Args, Color};
+use crate::render::indicator::{Indicator, IndicatorState};
+use crate::shm::slot::BufferSlot;
+
+pub struct IndicatorWidget {
+  indicator: Indicator,
+  idle_color: Color,
+  wrong_color: Color,
+  clear_color: Color,
+  verifying_color: Color,
+  grace_color: Color,
+}
+
+impl IndicatorWidget {
+  pub fn new(args: &Args) -> Self {
+    Self {
+      indicator: args.indicator(),
+      idle_color: args.indicator_idle_color,
+      wrong_color: args.indicator_wrong_color,
+      clear_color: args.indicator_clear_color,
+      verifying_color: args.indicator_verifying_color,
+      grace_color: args.indicator_grace_color,
+    }
+  }
+
+  fn state(&self, state: AppState, ctx: &WidgetContext) -> IndicatorState {
+    match state {
+      AppState::Idle if ctx.grace => IndicatorState::Full(self.grace_color),
+      AppState::Success | AppState::Idle => IndicatorState::Full(self.idle_color),
+      AppState::Invalid => IndicatorState::Full(self.wrong_color),
+      AppState::Verifying => IndicatorState::Full(self.verifying_color),
+      AppState::Input if ctx.password.is_empty() => IndicatorState::Full(self.clear_color),
+      AppState::Input => IndicatorState::Input(ctx.password.len()),
+    }
+  }
+}
+
+impl Widget for IndicatorWidget {
+  fn measure(&mut self, ctx: &WidgetContext, _output: (u32, u32)) -> (u32, u32) {
+    self.indicator.measure(self.state(ctx.state, ctx))
+  }
+
+  fn draw(&mut self, ctx: &WidgetContext, _buffer: &BufferSlot, context: &cairo::Context) {
+    let fade = ctx.frame.state_fade;
+    if fade >= 1.0 {
+      self.indicator.draw(context, self.state(ctx.state, ctx));
+      return;
+    }
+    // Cross-fade from the colours of the previous state
+    for (state, alpha) in [(ctx.previous_state, 1.0 - fade), (ctx.state, fade)] {
+      context.push_group();
+      self.indicator.draw(context, self.state(state, ctx));
+      context.pop_group_to_source().unwrap();
+      context.paint_with_alpha(alpha).unwrap();
+    }
+  }
+
+  fn reacts_to_state(&self) -> bool {
+    true
+  }
+}
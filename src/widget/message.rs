@@ -0,0 +1,38 @@
+use super::{Widget, WidgetContext};
+use crate::args::Args;
+use crate::render::text::{draw_paragraph, measure_paragraph, TextStyle};
+use crate::shm::slot::BufferSlot;
+
+pub use crate::render::text::TextAlign;
+
+pub struct MessageWidget {
+  text: String,
+  style: TextStyle,
+  align: TextAlign,
+  /// Percent of the output width
+  max_width: u32,
+}
+
+impl MessageWidget {
+  pub fn new(args: &Args, text: String) -> Self {
+    Self {
+      text,
+      style: TextStyle::new(args.message_color, &args.message_font, args.message_font_size),
+      align: args.message_align,
+      max_width: args.message_max_width,
+    }
+  }
+}
+
+impl Widget for MessageWidget {
+  fn measure(&mut self, _ctx: &WidgetContext, output: (u32, u32)) -> (u32, u32) {
+    let max_width = (output.0 as u64 * self.max_width as u64 / 100) as u32;
+    let metrics = measure_paragraph(&self.style, &self.text, max_width, self.align);
+    (std::cmp::max(metrics.width, 1), std::cmp::max(metrics.height, 1))
+  }
+
+  fn draw(&mut self, _ctx: &WidgetContext, buffer: &BufferSlot, context: &cairo::Context) {
+    // Wrapping at the measured width keeps the line breaks and aligns lines within the block
+    draw_paragraph(context, &self.style, &self.text, buffer.width(), self.align, 0.0, 0.0);
+  }
+}
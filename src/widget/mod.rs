@@ -0,0 +1,81 @@
+pub mod battery;
+pub mod clock;
+pub mod indicator;
+pub mod message;
+pub mod notice;
+pub mod password;
+pub mod user;
+
+use std::time::Duration;
+
+use crate::animation::AnimationFrame;
+use crate::application::AppState;
+use crate::args::Args;
+use crate::layout::Placement;
+use crate::shm::slot::BufferSlot;
+
+/// Application state shared with widgets when they are rendered
+pub struct WidgetContext<'a> {
+  pub state: AppState,
+  pub previous_state: AppState,
+  pub password: &'a str,
+  pub password_revealed: bool,
+  /// Any input unlocks without a password
+  pub grace: bool,
+  /// Problem to point out on every output
+  pub notice: Option<&'a str>,
+  pub frame: AnimationFrame,
+}
+
+/// Element drawn on its own subsurface of every output
+pub trait Widget {
+  /// Size of the buffer needed to draw the current content on an output of `output` size
+  fn measure(&mut self, ctx: &WidgetContext, output: (u32, u32)) -> (u32, u32);
+
+  /// Draw into a buffer slot of at least the measured size
+  fn draw(&mut self, ctx: &WidgetContext, buffer: &BufferSlot, context: &cairo::Context);
+
+  /// How often the widget needs to be redrawn regardless of state changes
+  fn refresh_interval(&self) -> Option<Duration> {
+    None
+  }
+
+  /// Update the content once the refresh interval elapsed, called before measuring
+  fn refresh(&mut self) {}
+
+  /// Whether the widget needs to be redrawn when the application state changes
+  fn reacts_to_state(&self) -> bool {
+    false
+  }
+
+  /// Whether the widget fades out while the seat is idle
+  fn hides_when_idle(&self) -> bool {
+    false
+  }
+}
+
+/// Widgets without a placement are stacked below the previous widget
+pub type WidgetEntry = (Box<dyn Widget>, Option<Placement>);
+
+/// Create the widgets for a single output in stacking order
+pub fn build(args: &Args, avatar: Option<&cairo::ImageSurface>) -> Vec<WidgetEntry> {
+  let layout = args.layout();
+  let mut widgets: Vec<WidgetEntry> = vec![
+    (Box::new(clock::ClockWidget::new(args)), Some(layout.clock)),
+    (Box::new(indicator::IndicatorWidget::new(args)), layout.indicator),
+  ];
+  if args.password_field {
+    widgets.push((Box::new(password::PasswordWidget::new(args)), layout.password));
+  }
+  if args.user {
+    widgets.push((Box::new(user::UserWidget::new(args, avatar.cloned())), layout.user));
+  }
+  if let Some(text) = args.message_text() {
+    widgets.push((Box::new(message::MessageWidget::new(args, text)), layout.message));
+  }
+  if args.battery {
+    widgets.push((Box::new(battery::BatteryWidget::new(args)), Some(layout.battery)));
+  }
+  widgets.push((Box::new(notice::NoticeWidget::new(args)), Some(notice::NoticeWidget::placement())));
+  widgets
+}
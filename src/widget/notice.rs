@@ -0,0 +1,46 @@
+use super::{Widget, WidgetContext};
+use crate::args::Args;
+use crate::layout::{Anchor, Placement};
+use crate::render::text::{draw_text, measure_text, TextStyle};
+use crate::shm::slot::BufferSlot;
+
+const NOTICE_MARGIN: i32 = 20;
+
+/// Short line at the bottom of the output for problems such as a config that failed to reload
+pub struct NoticeWidget {
+  style: TextStyle,
+}
+
+impl NoticeWidget {
+  pub fn new(args: &Args) -> Self {
+    Self {
+      style: TextStyle::new(args.indicator_wrong_color, &args.message_font, args.message_font_size),
+    }
+  }
+
+  pub fn placement() -> Placement {
+    Placement {
+      anchor: Anchor::Bottom,
+      margin: NOTICE_MARGIN,
+      ..Placement::default()
+    }
+  }
+}
+
+impl Widget for NoticeWidget {
+  fn measure(&mut self, ctx: &WidgetContext, _output: (u32, u32)) -> (u32, u32) {
+    match ctx.notice {
+      Some(notice) => {
+        let metrics = measure_text(&self.style, notice);
+        (std::cmp::max(metrics.width, 1), std::cmp::max(metrics.height, 1))
+      }
+      None => (1, 1),
+    }
+  }
+
+  fn draw(&mut self, ctx: &WidgetContext, _buffer: &BufferSlot, context: &cairo::Context) {
+    if let Some(notice) = ctx.notice {
+      draw_text(context, &self.style, notice, 0.0, 0.0);
+    }
+  }
+}
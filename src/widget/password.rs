@@ -0,0 +1,43 @@
+use super::{Widget, WidgetContext};
+use crate::args::Args;
+use crate::render::text::{draw_text, measure_text, TextStyle};
+use crate::shm::slot::BufferSlot;
+
+pub const PASSWORD_MASK_CHAR: char = '•';
+
+pub struct PasswordWidget {
+  style: TextStyle,
+  text: String,
+}
+
+impl PasswordWidget {
+  pub fn new(args: &Args) -> Self {
+    Self {
+      style: TextStyle::new(args.password_color, &args.password_font, args.password_font_size),
+      text: String::new(),
+    }
+  }
+}
+
+impl Widget for PasswordWidget {
+  fn measure(&mut self, ctx: &WidgetContext, _output: (u32, u32)) -> (u32, u32) {
+    self.text = if ctx.password_revealed {
+      ctx.password.to_owned()
+    } else {
+      std::iter::repeat(PASSWORD_MASK_CHAR)
+        .take(ctx.password.chars().count())
+        .collect()
+    };
+    let metrics = measure_text(&self.style, &self.text);
+    // Keep at least one pixel so an empty field still has a valid buffer
+    (std::cmp::max(metrics.width, 1), metrics.height)
+  }
+
+  fn draw(&mut self, _ctx: &WidgetContext, _buffer: &BufferSlot, context: &cairo::Context) {
+    draw_text(context, &self.style, &self.text, 0.0, 0.0);
+  }
+
+  fn reacts_to_state(&self) -> bool {
+    true
+  }
+}
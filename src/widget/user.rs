@@ -0,0 +1,160 @@
+use std::ffi::CStr;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::path::PathBuf;
+
+use users::os::unix::UserExt;
+use users::{get_current_uid, get_user_by_uid};
+
+use super::{Widget, WidgetContext};
+use crate::args::{Args, Color};
+use crate::render::text::{draw_text, measure_text, TextMetrics, TextStyle};
+use crate::shm::slot::BufferSlot;
+
+const NAME_SPACING: u32 = 12;
+
+pub struct UserWidget {
+  name: String,
+  initials: String,
+  avatar: Option<cairo::ImageSurface>,
+  avatar_size: u32,
+  avatar_color: Color,
+  style: TextStyle,
+  metrics: Option<TextMetrics>,
+}
+
+/// Picture of the current user, `~/.face` or the AccountsService icon, whichever is a PNG
+pub fn load_avatar() -> Option<cairo::ImageSurface> {
+  let user = get_user_by_uid(get_current_uid())?;
+  decode_avatar(&[
+    user.home_dir().join(".face"),
+    PathBuf::from("/var/lib/AccountsService/icons").join(user.name()),
+  ])
+}
+
+/// First of `paths` that decodes to a non-empty PNG
+fn decode_avatar(paths: &[PathBuf]) -> Option<cairo::ImageSurface> {
+  paths.iter().find_map(|path| {
+    let mut file = File::open(path).ok()?;
+    let image = cairo::ImageSurface::create_from_png(&mut file).ok()?;
+    (image.width() > 0 && image.height() > 0).then_some(image)
+  })
+}
+
+impl UserWidget {
+  /// `avatar` is decoded once by the application, see `load_avatar`
+  pub fn new(args: &Args, avatar: Option<cairo::ImageSurface>) -> Self {
+    let uid = get_current_uid();
+    let user = get_user_by_uid(uid).unwrap();
+    let username = user.name().to_string_lossy().into_owned();
+    let name = gecos_name(uid).unwrap_or_else(|| username.clone());
+    Self {
+      initials: initials(&name),
+      name,
+      avatar,
+      avatar_size: args.user_avatar_size,
+      avatar_color: args.user_avatar_color,
+      style: TextStyle::new(args.user_color, &args.user_font, args.user_font_size),
+      metrics: None,
+    }
+  }
+
+  fn draw_avatar(&self, context: &cairo::Context, x: f64) {
+    let size = self.avatar_size as f64;
+    let radius = size / 2.0;
+    context.save().unwrap();
+    context.new_sub_path();
+    context.arc(x + radius, radius, radius, 0.0, 2.0 * PI);
+    context.clip();
+    match self.avatar.as_ref() {
+      Some(image) => {
+        // Scale the shorter side to the avatar size and crop the longer one around the centre
+        let (width, height) = (image.width() as f64, image.height() as f64);
+        let side = width.min(height);
+        context.translate(x, 0.0);
+        context.scale(size / side, size / side);
+        context
+          .set_source_surface(image, -(width - side) / 2.0, -(height - side) / 2.0)
+          .unwrap();
+        context.paint().unwrap();
+      }
+      None => {
+        let color = self.avatar_color;
+        context.set_source_rgba(color.r, color.g, color.b, color.a);
+        context.paint().unwrap();
+        // Initials about 40% of the avatar height, a point is 4/3 of a pixel
+        let style = self.style.with_size(size * 0.3);
+        let metrics = measure_text(&style, &self.initials);
+        let text_x = x + (size - metrics.width as f64) / 2.0;
+        let text_y = (size - metrics.height as f64) / 2.0;
+        draw_text(context, &style, &self.initials, text_x, text_y);
+      }
+    }
+    context.restore().unwrap();
+  }
+}
+
+impl Widget for UserWidget {
+  fn measure(&mut self, _ctx: &WidgetContext, _output: (u32, u32)) -> (u32, u32) {
+    let metrics = measure_text(&self.style, &self.name);
+    self.metrics = Some(metrics);
+    (
+      std::cmp::max(self.avatar_size, metrics.width),
+      self.avatar_size + NAME_SPACING + metrics.height,
+    )
+  }
+
+  fn draw(&mut self, _ctx: &WidgetContext, buffer: &BufferSlot, context: &cairo::Context) {
+    let metrics = self.metrics.unwrap();
+    let avatar_x = (buffer.width() - self.avatar_size) / 2;
+    self.draw_avatar(context, avatar_x as f64);
+    let name_x = (buffer.width() - metrics.width) / 2;
+    let name_y = self.avatar_size + NAME_SPACING;
+    draw_text(context, &self.style, &self.name, name_x as f64, name_y as f64);
+  }
+}
+
+/// Full name from the first field of the GECOS entry
+fn gecos_name(uid: u32) -> Option<String> {
+  let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+  let mut buf = vec![0 as libc::c_char; 4096];
+  let mut result = std::ptr::null_mut();
+  let ret = unsafe { libc::getpwuid_r(uid, &mut passwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+  if ret != 0 || result.is_null() || passwd.pw_gecos.is_null() {
+    return None;
+  }
+  let gecos = unsafe { CStr::from_ptr(passwd.pw_gecos) }.to_string_lossy();
+  let name = gecos.split(',').next().unwrap_or_default().trim();
+  (!name.is_empty()).then(|| name.to_owned())
+}
+
+fn initials(name: &str) -> String {
+  name
+    .split_whitespace()
+    .filter_map(|word| word.chars().next())
+    .take(2)
+    .flat_map(char::to_uppercase)
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn avatar_skips_undecodable_candidates() {
+    let dir = std::env::temp_dir().join(format!("minlock-avatar-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let jpeg = dir.join("face.jpg");
+    std::fs::write(&jpeg, [0xff, 0xd8, 0xff, 0xe0, 0, 0x10, b'J', b'F', b'I', b'F', 0]).unwrap();
+    let png = dir.join("icon.png");
+    let image = cairo::ImageSurface::create(cairo::Format::ARgb32, 3, 2).unwrap();
+    image.write_to_png(&mut File::create(&png).unwrap()).unwrap();
+
+    let missing = dir.join("missing");
+    let avatar = decode_avatar(&[missing.clone(), jpeg.clone(), png]).unwrap();
+    assert_eq!((avatar.width(), avatar.height()), (3, 2));
+    assert!(decode_avatar(&[missing, jpeg]).is_none());
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}